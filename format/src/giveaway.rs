@@ -0,0 +1,193 @@
+use std::{collections::HashMap, ops::Add};
+use serde::{Serialize, Deserialize, Deserializer};
+
+/// The general information about a gleam campaign.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Campaign {
+    pub key: String,
+    pub name: String,
+    pub starts_at: u64,
+    pub ends_at: u64,
+    #[serde(default)]
+    pub language: String,
+    #[serde(default)]
+    pub site_url: String,
+    #[serde(default)]
+    pub site_name: String,
+    #[serde(default)]
+    pub finished: bool,
+    #[serde(default)]
+    pub paused: bool,
+    #[serde(default)]
+    pub login_types: Vec<String>,
+    #[serde(default)]
+    pub stand_alone_url: String,
+    #[serde(default)]
+    pub campaign_type: String,
+    #[serde(default)]
+    pub terms_and_conditions: String,
+    #[serde(default)]
+    pub announce_winners: bool,
+}
+
+/// What can be won.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Incentive {
+    pub incentive_name: String,
+    #[serde(default)]
+    pub incentive_description: String,
+    #[serde(default)]
+    pub incentive_type: String,
+}
+
+/// An action giving entries to the contestants.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntryMethod {
+    pub entry_type: String,
+    pub worth: i64,
+    pub config1: Option<String>,
+    pub config2: Option<String>,
+    pub config3: Option<String>,
+    pub config4: Option<String>,
+    pub config5: Option<String>,
+    pub config6: Option<String>,
+    pub config7: Option<String>,
+    pub config8: Option<String>,
+    pub config9: Option<String>,
+    pub method_type: Option<String>,
+    #[serde(default)]
+    pub interval_seconds: u64,
+    #[serde(default)]
+    pub actions_required: u64,
+    #[serde(default)]
+    pub template: String,
+    #[serde(default)]
+    pub normal_icon: String,
+    #[serde(default)]
+    pub normal_icon_color: String,
+    #[serde(default)]
+    pub mandatory: bool,
+    pub workflow: Option<String>,
+    pub timer_action: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Giveaway {
+    #[serde(flatten)]
+    pub campaign: Campaign,
+    pub entry_methods: Vec<EntryMethod>,
+    #[serde(flatten)]
+    pub incentive: Incentive,
+}
+
+/// A giveaway with what the crawler knows about it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    pub giveaway: Giveaway,
+    /// Timestamp of the last time the giveaway was loaded
+    pub last_updated: u64,
+    /// Left empty by the crawler, which stores the referers with their dates beside the giveaways
+    pub referers: Vec<String>,
+    pub entry_count: Option<usize>,
+    /// The entry count of the giveaway at each time it was loaded
+    #[serde(deserialize_with = "deserialize_entry_evolution")]
+    pub entry_evolution: Option<HashMap<u64, usize>>,
+}
+
+/// JSON objects have string keys, which serde can't turn back into integers once flattened.
+fn deserialize_entry_evolution<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<HashMap<u64, usize>>, D::Error> {
+    let entry_evolution: Option<HashMap<String, usize>> = Option::deserialize(deserializer)?;
+    entry_evolution
+        .map(|entry_evolution| {
+            entry_evolution
+                .into_iter()
+                .map(|(time, entries)| time.parse().map(|time| (time, entries)).map_err(serde::de::Error::custom))
+                .collect()
+        })
+        .transpose()
+}
+
+impl SearchResult {
+    pub fn ends_at(&self) -> u64 {
+        self.giveaway.campaign.ends_at
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.giveaway.campaign.name
+    }
+
+    /// The canonical url of the giveaway.
+    pub fn get_url(&self) -> String {
+        format!("https://gleam.io/{}/-", self.giveaway.campaign.key)
+    }
+}
+
+/// Merge two versions of the same giveaway.
+/// The most recently updated one is kept, with the referers and the entry evolutions of both.
+impl Add for SearchResult {
+    type Output = SearchResult;
+
+    fn add(self, other: SearchResult) -> SearchResult {
+        let (older, mut newer) = if self.last_updated > other.last_updated { (other, self) } else { (self, other) };
+
+        for referer in older.referers {
+            if !newer.referers.contains(&referer) {
+                newer.referers.push(referer);
+            }
+        }
+        newer.entry_evolution = match (older.entry_evolution, newer.entry_evolution) {
+            (Some(mut entry_evolution), Some(newer_evolution)) => {
+                entry_evolution.extend(newer_evolution);
+                Some(entry_evolution)
+            },
+            (entry_evolution, None) | (None, entry_evolution) => entry_evolution,
+        };
+        if newer.entry_count.is_none() {
+            newer.entry_count = older.entry_count;
+        }
+        newer
+    }
+}
+
+impl meilisearch_sdk::document::Document for SearchResult {
+    type UIDType = String;
+
+    fn get_uid(&self) -> &String {
+        &self.giveaway.campaign.key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_giveaways() {
+        let content = std::fs::read_to_string("../new_giveaways.json").unwrap();
+        let giveaways: Vec<SearchResult> = serde_json::from_str(&content).unwrap();
+        assert_eq!(giveaways[0].get_url(), "https://gleam.io/K5Ihy/-");
+        assert_eq!(giveaways[0].entry_evolution.as_ref().unwrap()[&1603368228], 721);
+
+        let saved = serde_json::to_value(&giveaways).unwrap();
+        assert_eq!(saved, serde_json::from_str::<serde_json::Value>(&content).unwrap());
+    }
+
+    #[test]
+    fn merge() {
+        let content = std::fs::read_to_string("../new_giveaways.json").unwrap();
+        let giveaway: SearchResult = serde_json::from_str::<Vec<SearchResult>>(&content).unwrap().remove(0);
+        let mut updated = giveaway.clone();
+        updated.last_updated += 3600;
+        updated.referers = vec![String::from("https://example.com/giveaways")];
+        updated.entry_count = Some(800);
+        updated.entry_evolution = Some(vec![(updated.last_updated, 800)].into_iter().collect());
+
+        let merged = giveaway.clone() + updated.clone();
+        assert_eq!(merged, updated.clone() + giveaway.clone());
+        assert_eq!(merged.last_updated, updated.last_updated);
+        assert_eq!(merged.entry_count, Some(800));
+        assert_eq!(merged.referers, vec![String::from("https://example.com/giveaways"), String::from("https://gleam.io/K5Ihy/-")]);
+        assert_eq!(merged.entry_evolution.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,8 @@
+//! The format of the giveaways shared by the crawler and the other gleam finder programs.
+
+pub mod giveaway;
+pub mod parsing;
+
+pub mod prelude {
+    pub use crate::giveaway::{SearchResult, Giveaway, Campaign, Incentive, EntryMethod};
+}
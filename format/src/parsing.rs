@@ -0,0 +1,68 @@
+use serde::Deserialize;
+use crate::giveaway::{Giveaway, Campaign, Incentive, EntryMethod};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// The page doesn't contain the data of a giveaway, which happens when the giveaway was deleted
+    GiveawayJsonNotFound,
+    /// The data of the giveaway doesn't have the expected format
+    InvalidGiveawayJson(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct RawIncentive {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    incentive_type: String,
+}
+
+/// The data passed to the `initCampaign` function of the page of a giveaway.
+#[derive(Debug, Deserialize)]
+struct CampaignData {
+    campaign: Campaign,
+    incentive: RawIncentive,
+    #[serde(default)]
+    entry_methods: Vec<EntryMethod>,
+}
+
+impl From<CampaignData> for Giveaway {
+    fn from(data: CampaignData) -> Giveaway {
+        Giveaway {
+            campaign: data.campaign,
+            entry_methods: data.entry_methods,
+            incentive: Incentive {
+                incentive_name: data.incentive.name,
+                incentive_description: data.incentive.description,
+                incentive_type: data.incentive.incentive_type,
+            },
+        }
+    }
+}
+
+/// The text between `start` and the next `end`.
+fn between<'a>(text: &'a str, start: &str, end: &str) -> Option<&'a str> {
+    let text = &text[text.find(start)? + start.len()..];
+    Some(&text[..text.find(end)?])
+}
+
+/// Decode the entities used in the attributes of gleam pages.
+fn decode_entities(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Parse the page of a giveaway, returning the giveaway and its entry count if the page tells it.
+pub fn parse_html(html: &str) -> Result<(Giveaway, Option<usize>), ParseError> {
+    let json = between(html, "ng-init=\"initCampaign(", ")\"").ok_or(ParseError::GiveawayJsonNotFound)?;
+    let data: CampaignData = serde_json::from_str(&decode_entities(json)).map_err(|e| ParseError::InvalidGiveawayJson(e.to_string()))?;
+    let entry_count = between(html, "initEntryCount(", ")").and_then(|count| count.trim().parse().ok());
+
+    Ok((data.into(), entry_count))
+}
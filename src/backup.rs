@@ -0,0 +1,292 @@
+use std::{collections::HashSet, fs::{self, File}, io, path::{Path, PathBuf}, process::exit, time::SystemTime};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use crate::{config::*, database::{open_database, JsonDatabase, SqliteDatabase}};
+
+const HOUR: u64 = 3600;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+pub(crate) struct Snapshot {
+    pub(crate) path: PathBuf,
+    pub(crate) timestamp: u64,
+    /// The number of the snapshots taken earlier in the same second
+    pub(crate) sequence: u32,
+}
+
+fn snapshot_prefix(config: &Config) -> String {
+    let file_name = Path::new(&config.database_file).file_name().map(|n| n.to_string_lossy().to_string());
+    format!("{}-", file_name.unwrap_or_else(|| String::from("database")))
+}
+
+/// The path of a new snapshot taken at `timestamp`.
+/// The snapshots taken in the same second are numbered instead of replacing each other.
+fn snapshot_path(config: &Config, backup_config: &BackupConfig, timestamp: u64) -> PathBuf {
+    let prefix = snapshot_prefix(config);
+    let mut path = Path::new(&backup_config.directory).join(format!("{}{}.gz", prefix, timestamp));
+    let mut sequence = 0;
+    while path.exists() {
+        sequence += 1;
+        path = Path::new(&backup_config.directory).join(format!("{}{}-{}.gz", prefix, timestamp, sequence));
+    }
+    path
+}
+
+/// List the snapshots of the database, the most recent first.
+pub(crate) fn list_snapshots(config: &Config, backup_config: &BackupConfig) -> Vec<Snapshot> {
+    let prefix = snapshot_prefix(config);
+    let entries = match fs::read_dir(&backup_config.directory) {
+        Ok(entries) => entries,
+        Err(_e) => return Vec::new(),
+    };
+
+    let mut snapshots = Vec::new();
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if let Some(name) = file_name.strip_prefix(&prefix).and_then(|n| n.strip_suffix(".gz")) {
+            let (timestamp, sequence) = match name.split_once('-') {
+                Some((timestamp, sequence)) => (timestamp.parse(), sequence.parse()),
+                None => (name.parse(), Ok(0)),
+            };
+            if let (Ok(timestamp), Ok(sequence)) = (timestamp, sequence) {
+                snapshots.push(Snapshot {
+                    path: entry.path(),
+                    timestamp,
+                    sequence,
+                });
+            }
+        }
+    }
+    snapshots.sort_by_key(|s| std::cmp::Reverse((s.timestamp, s.sequence)));
+    snapshots
+}
+
+/// Select the snapshots to keep: the most recent one of each of the last `hourly` hours, `daily` days and `weekly` weeks.
+/// `timestamps` must be sorted from the most recent to the oldest.
+fn snapshots_to_keep(timestamps: &[u64], hourly: usize, daily: usize, weekly: usize) -> HashSet<u64> {
+    let mut kept = HashSet::new();
+    for (period, count) in &[(HOUR, hourly), (DAY, daily), (WEEK, weekly)] {
+        let mut last_bucket = None;
+        let mut buckets = 0;
+        for timestamp in timestamps {
+            if buckets >= *count {
+                break;
+            }
+            let bucket = timestamp / period;
+            if last_bucket != Some(bucket) {
+                last_bucket = Some(bucket);
+                buckets += 1;
+                kept.insert(*timestamp);
+            }
+        }
+    }
+    kept
+}
+
+/// Write a compressed snapshot of the database and delete the snapshots which are not needed anymore.
+/// The database must have been compacted.
+pub(crate) fn backup(config: &Config, backup_config: &BackupConfig) -> io::Result<PathBuf> {
+    let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+    fs::create_dir_all(&backup_config.directory)?;
+    let path = snapshot_path(config, backup_config, timestamp);
+    let temporary_path = path.with_extension("gz.tmp");
+
+    let mut database = File::open(&config.database_file)?;
+    let mut encoder = GzEncoder::new(File::create(&temporary_path)?, Compression::default());
+    io::copy(&mut database, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    fs::rename(&temporary_path, &path)?;
+
+    let snapshots = list_snapshots(config, backup_config);
+    let timestamps = snapshots.iter().map(|s| s.timestamp).collect::<Vec<u64>>();
+    let mut kept = snapshots_to_keep(&timestamps, backup_config.keep_hourly, backup_config.keep_daily, backup_config.keep_weekly);
+    for snapshot in snapshots {
+        // Only the most recent of the snapshots taken in the same second is kept
+        if !kept.remove(&snapshot.timestamp) {
+            fs::remove_file(&snapshot.path)?;
+        }
+    }
+
+    Ok(path)
+}
+
+/// Decompress a snapshot to `destination` and check that it is a valid database.
+/// Returns the number of giveaways it contains.
+fn extract_snapshot(snapshot: &Path, destination: &str, config: &Config) -> Result<usize, String> {
+    let mut decoder = GzDecoder::new(File::open(snapshot).map_err(|e| e.to_string())?);
+    let mut file = File::create(destination).map_err(|e| e.to_string())?;
+    io::copy(&mut decoder, &mut file).map_err(|e| e.to_string())?;
+    file.sync_all().map_err(|e| e.to_string())?;
+
+    match config.database_backend {
+        DatabaseBackend::Json => JsonDatabase::check(destination),
+        DatabaseBackend::Sqlite => SqliteDatabase::check(destination),
+    }
+}
+
+/// The suffix of the files replaced by a restoration.
+/// It is unique so that restoring several times in a row doesn't lose the files replaced by the previous restorations.
+fn before_restore_suffix(config: &Config) -> String {
+    let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+    let mut suffix = format!("before-restore-{}", timestamp);
+    let mut sequence = 0;
+    while Path::new(&format!("{}.{}", config.database_file, suffix)).exists() || Path::new(&format!("{}.journal.{}", config.database_file, suffix)).exists() {
+        sequence += 1;
+        suffix = format!("before-restore-{}-{}", timestamp, sequence);
+    }
+    suffix
+}
+
+pub(crate) fn backup_command(config: &Config) {
+    open_database(config).compact();
+    match backup(config, &config.backup) {
+        Ok(path) => println!("Database saved to {}", path.display()),
+        Err(e) => {
+            eprintln!("Failed to backup the database: {}", e);
+            exit(1);
+        }
+    }
+}
+
+pub(crate) fn restore_command(config: &Config, snapshot: Option<&str>) {
+    let backup_config = &config.backup;
+    let snapshots = list_snapshots(config, backup_config);
+
+    let snapshot = match snapshot {
+        Some(snapshot) => snapshot,
+        None => {
+            if snapshots.is_empty() {
+                println!("No snapshot in {}", backup_config.directory);
+            }
+            for snapshot in snapshots {
+                let size = fs::metadata(&snapshot.path).map(|m| m.len()).unwrap_or(0);
+                println!("{}\t{:>10} bytes\t{}", snapshot.timestamp, size, snapshot.path.display());
+            }
+            return;
+        }
+    };
+
+    let snapshot = match snapshots.iter().find(|s| s.timestamp.to_string() == snapshot || s.path.file_name().map(|n| n == snapshot).unwrap_or(false)) {
+        Some(snapshot) => snapshot,
+        None => {
+            eprintln!("No snapshot matching {} in {}", snapshot, backup_config.directory);
+            exit(1);
+        }
+    };
+
+    let restored_path = format!("{}.restore", config.database_file);
+    match extract_snapshot(&snapshot.path, &restored_path, config) {
+        Ok(count) => println!("Snapshot {} is valid ({} giveaways)", snapshot.path.display(), count),
+        Err(e) => {
+            let _ = fs::remove_file(&restored_path);
+            eprintln!("Snapshot {} is invalid: {}", snapshot.path.display(), e);
+            exit(1);
+        }
+    }
+
+    // Keep the current database in case the restored one is not the expected one
+    let suffix = before_restore_suffix(config);
+    if Path::new(&config.database_file).exists() {
+        let previous_path = format!("{}.{}", config.database_file, suffix);
+        if let Err(e) = fs::rename(&config.database_file, &previous_path) {
+            eprintln!("Failed to move the current database: {}", e);
+            exit(1);
+        }
+        println!("Current database moved to {}", previous_path);
+    }
+    // The changes journaled since the current snapshot don't apply to the restored one
+    let journal_path = format!("{}.journal", config.database_file);
+    if Path::new(&journal_path).exists() {
+        let previous_path = format!("{}.{}", journal_path, suffix);
+        if let Err(e) = fs::rename(&journal_path, &previous_path) {
+            eprintln!("Failed to move the current database journal: {}", e);
+            exit(1);
+        }
+        println!("Current database journal moved to {}", previous_path);
+    }
+    if let Err(e) = fs::rename(&restored_path, &config.database_file) {
+        eprintln!("Failed to restore the database: {}", e);
+        exit(1);
+    }
+    println!("Database restored from {}", snapshot.path.display());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+
+    #[test]
+    fn rotation() {
+        let now = 100 * WEEK + 3 * DAY + 12 * HOUR;
+        let timestamps = (0..24 * 60).map(|i| now - i * HOUR).collect::<Vec<u64>>();
+        let kept = snapshots_to_keep(&timestamps, 3, 2, 2);
+
+        let mut expected = HashSet::new();
+        expected.insert(now);
+        expected.insert(now - HOUR);
+        expected.insert(now - 2 * HOUR);
+        expected.insert(now - 13 * HOUR);
+        expected.insert(now - 85 * HOUR);
+        assert_eq!(kept, expected);
+
+        assert!(snapshots_to_keep(&timestamps, 0, 0, 0).is_empty());
+        assert!(snapshots_to_keep(&[], 5, 5, 5).is_empty());
+    }
+
+    #[test]
+    fn same_second() {
+        let directory = std::env::temp_dir().join(format!("backups-{}", std::process::id()));
+        let config: Config = toml::from_str("database_file = \"database.json\"").unwrap();
+        let backup_config = BackupConfig {
+            directory: directory.to_str().unwrap().to_string(),
+            ..BackupConfig::default()
+        };
+        fs::create_dir_all(&directory).unwrap();
+
+        for _ in 0..3 {
+            fs::write(snapshot_path(&config, &backup_config, 1600000000), "").unwrap();
+        }
+        fs::write(snapshot_path(&config, &backup_config, 1600000001), "").unwrap();
+        let snapshots = list_snapshots(&config, &backup_config);
+        fs::remove_dir_all(&directory).unwrap();
+
+        let names = snapshots.iter().map(|s| s.path.file_name().unwrap().to_str().unwrap().to_string()).collect::<Vec<_>>();
+        assert_eq!(names, vec![
+            "database.json-1600000001.gz",
+            "database.json-1600000000-2.gz",
+            "database.json-1600000000-1.gz",
+            "database.json-1600000000.gz",
+        ]);
+    }
+
+    #[test]
+    fn restoring_twice() {
+        let directory = std::env::temp_dir().join(format!("restores-{}", std::process::id()));
+        let database_file = directory.join("database.json");
+        let config: Config = toml::from_str(&format!(
+            "database_file = {:?}\n[backup]\ndirectory = {:?}",
+            database_file.to_str().unwrap(),
+            directory.join("backups").to_str().unwrap(),
+        )).unwrap();
+        assert!(config.backup.automatic);
+        fs::create_dir_all(&directory).unwrap();
+        JsonDatabase::open(&config.database_file, 1000, false).compact();
+        backup(&config, &config.backup).unwrap();
+        let timestamp = list_snapshots(&config, &config.backup)[0].timestamp.to_string();
+
+        // Each restoration keeps the database it replaces
+        restore_command(&config, Some(&timestamp));
+        fs::write(format!("{}.journal", config.database_file), "").unwrap();
+        restore_command(&config, Some(&timestamp));
+        let mut replaced = fs::read_dir(&directory).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name.contains(".before-restore-"))
+            .collect::<Vec<_>>();
+        replaced.sort();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(replaced.len(), 3);
+        assert!(replaced[0].starts_with("database.json.before-restore-"));
+        assert!(replaced[2].starts_with("database.json.journal.before-restore-"));
+    }
+}
@@ -1,284 +1,809 @@
-use crate::{config::*, google, gleam, meilisearch::*, database::*};
-use std::{collections::HashMap, time::{Instant, Duration, SystemTime}, thread::sleep};
+use crate::{config::*, discovery::{self, DiscoverySource, DiscoveryState}, gleam, meilisearch::*, database::*, backup::backup, queue::*, rate_limiter::RateLimiter, scheduler::{Scheduler, Task}, shutdown::Shutdown, refresh::select_giveaways_to_refresh, seen::*, robots::{self, RobotsCache}, extract::{extract_gleam_links, extract_embedded_gleam_links, extract_urls}, shorteners::{self, ShortenerCache}, retry::{Retrier, Failure, RequestError}, proxies::{ProxyPool, HttpClients}, http::{self, HttpClient}, hosts::HostStore, sitemaps::{self, Sitemap}, feeds::{self, FeedStore}, util::url_to_host};
+use std::{collections::HashSet, sync::Arc, time::{Duration, SystemTime}};
+use futures::{future::{self, Future}, stream::{self, StreamExt}};
 use progress_bar::{color::*, progress_bar::ProgressBar};
-use url::Url;
+use url::{Url, Position};
 use format::{prelude::*, parsing::*};
 
-fn url_to_host(url: &str) -> String {
-    if let Ok(url) = &Url::parse(url) {
-        url.host_str().unwrap_or("unknown").to_string()
-    } else {
-        "unknown".to_string()
-    }
+fn now() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs()
 }
 
-fn search_google_results(cooldown: u64) -> Vec<String> {
-    let mut progress_bar = ProgressBar::new(7);
-    progress_bar.set_action("Searching", Color::White, Style::Normal);
-    let mut results = Vec::new();
-    let mut page = 0;
-    loop {
+/// Sitemap indexes nested deeper than this are not followed.
+const MAX_SITEMAP_DEPTH: usize = 3;
+
+/// The state shared by the stages of the crawler.
+struct Crawler {
+    config: Config,
+    sources: Vec<Box<dyn DiscoverySource>>,
+    proxies: ProxyPool,
+    rate_limiter: RateLimiter,
+    retrier: Retrier,
+    robots: RobotsCache,
+    shorteners: ShortenerCache,
+    hosts: HostStore,
+    shutdown: Shutdown,
+}
+
+impl Crawler {
+    fn new(config: Config) -> Crawler {
+        Crawler {
+            sources: discovery::sources(&config),
+            proxies: ProxyPool::new(&config),
+            rate_limiter: RateLimiter::new(Duration::from_secs(config.cooldown as u64)),
+            retrier: Retrier::new(config.retry.clone()),
+            robots: RobotsCache::new(),
+            shorteners: ShortenerCache::open(&config.shortener_cache_file),
+            hosts: HostStore::open(&config.hosts),
+            shutdown: Shutdown::listen(),
+            config,
+        }
+    }
+
+    /// Search results with the enabled discovery sources and add them to the queue, without duplicates.
+    async fn search_results(&self, queue: &mut Queue) {
+        // The number of runs is saved so that one-shot runs rotate the queries too
+        let mut state = DiscoveryState::open(&self.config.discovery.state_file);
+        for source in &self.sources {
+            source.new_cycle(state.runs);
+        }
+        state.runs += 1;
+        state.save();
+        let mut progress_bar = ProgressBar::new(self.sources.iter().map(|source| source.max_pages()).sum());
+        progress_bar.set_action("Searching", Color::White, Style::Normal);
+        let mut found = HashSet::new();
+
+        for source in &self.sources {
+            let source = source.as_ref();
+            let mut result_count = 0;
+            // The results of this source, which tell when its pages stop bringing anything new
+            let mut source_found = HashSet::new();
+            for page in 0..source.max_pages() {
+                if self.shutdown.requested() {
+                    break;
+                }
+                if page > 0 {
+                    progress_bar.set_action("Sleeping", Color::Yellow, Style::Normal);
+                    self.shutdown.sleep(source.cooldown()).await;
+                }
+                progress_bar.set_action("Loading", Color::Blue, Style::Normal);
+                progress_bar.print_info("Getting", &format!("the results page {} of {}", page, source.name()), Color::Blue, Style::Normal);
+                let new_results = self.with_retries(&url_to_host(&source.page_url(page)), move |clients| async move {
+                    discovery::search(source, &*clients.client, page).await
+                }).await;
+                let new_results = match new_results {
+                    Ok(new_results) => new_results,
+                    Err(Failure::Interrupted) => break,
+                    Err(e) => {
+                        progress_bar.print_info("Error", &format!("when searching on {}: {}", source.name(), e), Color::Red, Style::Normal);
+                        break;
+                    }
+                };
+                progress_bar.inc();
+
+                // The last page is often repeated past the end of the results
+                let new_results = new_results.iter().filter_map(|result| discovery::normalize(result)).filter(|result| source_found.insert(result.clone())).collect::<Vec<_>>();
+                if new_results.is_empty() {
+                    break;
+                }
+
+                // The results already found by another source are not queued twice
+                let new_results = new_results.into_iter().filter(|result| found.insert(result.clone())).collect::<Vec<_>>();
+                result_count += new_results.len();
+                for new_result in new_results {
+                    queue.add_result(new_result);
+                }
+                queue.checkpoint();
+            }
+            progress_bar.print_info("Searched", &format!("{} new results on {}", result_count, source.name()), Color::White, Style::Normal);
+        }
+        queue.save();
+        progress_bar.set_action("Finished", Color::Green, Style::Bold);
+        progress_bar.print_info("Finished", &format!("{} results found", found.len()), Color::Green, Style::Bold);
+        progress_bar.finalize();
+        println!();
+    }
+
+    /// Add the seed pages and the pages of the sitemaps which changed recently to the queue.
+    async fn load_seeds(&self, queue: &mut Queue) {
+        if self.config.seeds.is_empty() && self.config.sitemaps.is_empty() {
+            return;
+        }
+        let mut progress_bar = ProgressBar::new(self.config.sitemaps.len());
+        progress_bar.set_action("Seeding", Color::White, Style::Normal);
+        for seed in &self.config.seeds {
+            queue.add_result(seed.clone());
+        }
+        let mut result_count = self.config.seeds.len();
+
+        let since = now().saturating_sub(self.config.sitemap_max_age);
+        let mut sitemaps = self.config.sitemaps.iter().rev().map(|url| (url.clone(), 0)).collect::<Vec<_>>();
+        let mut visited = HashSet::new();
         progress_bar.set_action("Loading", Color::Blue, Style::Normal);
-        progress_bar.print_info("Getting", &format!("the results page {}", page), Color::Blue, Style::Normal);
-        let new_results = google::search(page).unwrap_or_default();
-        if !new_results.is_empty() {
-            for new_result in new_results {
-                results.push(new_result);
+        while let Some((url, depth)) = sitemaps.pop() {
+            if self.shutdown.requested() {
+                break;
+            }
+            if depth == 0 {
+                progress_bar.inc();
+            }
+            if !visited.insert(url.clone()) {
+                continue;
+            }
+
+            let url = url.as_str();
+            let sitemap = self.with_retries(&url_to_host(url), move |clients| async move {
+                sitemaps::fetch(&*clients.client, url).await
+            }).await;
+            match sitemap {
+                Ok(Sitemap::Index(entries)) if depth < MAX_SITEMAP_DEPTH => {
+                    for entry in entries.into_iter().rev().filter(|entry| entry.changed_since(since)) {
+                        sitemaps.push((entry.loc, depth + 1));
+                    }
+                },
+                Ok(Sitemap::Index(_entries)) => {
+                    progress_bar.print_info("Ignored", &format!("sitemap index {} nested too deeply", url), Color::Yellow, Style::Normal);
+                },
+                Ok(Sitemap::UrlSet(entries)) => {
+                    for entry in entries.into_iter().filter(|entry| entry.changed_since(since)) {
+                        queue.add_result(entry.loc);
+                        result_count += 1;
+                    }
+                },
+                Err(e) => {
+                    progress_bar.print_info("Error", &format!("when trying to load the sitemap {}: {}", url, e), Color::Red, Style::Normal);
+                },
+            }
+        }
+        queue.save();
+        progress_bar.set_action("Finished", Color::Green, Style::Bold);
+        progress_bar.print_info("Finished", &format!("{} seed pages found", result_count), Color::Green, Style::Bold);
+        progress_bar.finalize();
+        println!();
+    }
+
+    /// Poll the feeds and queue the gleam links found in their new items, as well as the pages of these items.
+    async fn load_feeds(&self, queue: &mut Queue) {
+        if self.config.feeds.is_empty() {
+            return;
+        }
+        let mut store = FeedStore::open(&self.config.feeds_file);
+        let mut progress_bar = ProgressBar::new(self.config.feeds.len());
+        progress_bar.set_action("Loading", Color::Blue, Style::Normal);
+        let mut item_count = 0;
+        let mut link_count = 0;
+
+        for feed in &self.config.feeds {
+            if self.shutdown.requested() {
+                break;
+            }
+            let url = feed.as_str();
+            let items = self.with_retries(&url_to_host(url), move |clients| async move {
+                feeds::fetch(&*clients.client, url).await
+            }).await;
+            match items {
+                Ok(items) => {
+                    for item in store.new_items(feed, items, now()) {
+                        item_count += 1;
+                        let referer = item.link.clone().unwrap_or_else(|| feed.clone());
+                        for gleam_link in item.gleam_links {
+                            let link = QueuedLink {
+                                url: gleam_link,
+                                referers: vec![Referer::new(referer.clone(), now(), false)],
+                            };
+                            if queue.add_link(link) {
+                                link_count += 1;
+                            }
+                        }
+                        // The page may contain links missing from the feed
+                        if let Some(link) = item.link {
+                            queue.add_result(link);
+                        }
+                    }
+                },
+                Err(e) => {
+                    progress_bar.print_info("Error", &format!("when trying to load the feed {}: {}", feed, e), Color::Red, Style::Normal);
+                },
             }
-            page += 1;
             progress_bar.inc();
-            progress_bar.set_action("Sleeping", Color::Yellow, Style::Normal);
-            sleep(Duration::from_secs(cooldown));
-        } else {
-            break;
         }
+        queue.save();
+        store.save();
+        progress_bar.set_action("Finished", Color::Green, Style::Bold);
+        progress_bar.print_info("Finished", &format!("{} new feed items, {} gleam links found", item_count, link_count), Color::Green, Style::Bold);
+        progress_bar.finalize();
+        println!();
     }
-    progress_bar.set_action("Finished", Color::Green, Style::Bold);
-    progress_bar.print_info("Finished", &format!("{} results found", results.len()), Color::Green, Style::Bold);
-    progress_bar.finalize();
-    println!();
 
-    results
-}
+    /// Send a request to `host`, respecting its cooldown, and retry it with an exponential backoff while it fails with transient errors.
+    /// Each attempt goes through the proxy chosen by the rotation strategy, which is blamed only for connection errors.
+    /// Hosts which keep failing are not requested until their cool-off has passed.
+    async fn with_retries<T, E, F, Fut>(&self, host: &str, mut request: F) -> Result<T, Failure<E>>
+    where
+        E: RequestError,
+        F: FnMut(HttpClients) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut failures = 0;
+        loop {
+            if !self.retrier.allows(host) {
+                return Err(Failure::CircuitOpen);
+            }
+            if self.shutdown.interruptible(self.rate_limiter.wait(host)).await.is_none() {
+                return Err(Failure::Interrupted);
+            }
 
-fn load_results(results: Vec<String>, config: &Config, giveaways: &mut HashMap<String, SearchResult>, outdated_meilisearch: &mut Vec<String>, fast: bool) {
-    let cooldown = config.cooldown as u64;
-
-    let mut progress_bar = ProgressBar::new(results.len());
-    let mut timeout_check = HashMap::new();
-    let mut last_gleam_request = Instant::now();
-    progress_bar.set_action("Loading", Color::White, Style::Normal);
-    for result in &results {
-        // Check the cooldown
-        if let Some(last_load_time) = timeout_check.get(&url_to_host(&result)) {
-            let time_since_last_load = Instant::now() - *last_load_time;
-            if time_since_last_load < Duration::from_secs(cooldown) {
-                let time_to_sleep = Duration::from_secs(cooldown) - time_since_last_load;
-                progress_bar.set_action("Sleeping", Color::Yellow, Style::Normal); 
-                sleep(time_to_sleep);
+            let (proxy, clients) = match self.proxies.pick(host) {
+                Some(picked) => picked,
+                None => return Err(Failure::NoProxy),
+            };
+            match request(clients).await {
+                Err(e) if e.is_transient() => {
+                    // The errors of the destination are not the fault of the proxy
+                    self.proxies.report(proxy, !e.is_connection_error());
+                    failures += 1;
+                    if self.retrier.failure(host) {
+                        return Err(Failure::Error(e));
+                    }
+                    match self.retrier.backoff(failures) {
+                        Some(delay) => self.shutdown.sleep(delay).await,
+                        None => return Err(Failure::Error(e)),
+                    }
+                },
+                // The host answered, even if the answer is not the expected one
+                result => {
+                    self.proxies.report(proxy, true);
+                    self.retrier.success(host);
+                    return result.map_err(Failure::Error);
+                },
             }
         }
-        
-        // Load the page
-        progress_bar.set_action("Loading", Color::Blue, Style::Normal);
-        let giveaway_urls = match resolve(result) {
-            Ok(urls) => urls,
-            Err(e) => {
-                progress_bar.print_info("Error", &format!("when trying to load {}: {}", result, e), Color::Red, Style::Normal);
-                continue;
+    }
+
+    /// Whether the robots.txt file of the host of `url` allows loading it.
+    /// The rules are cached and their crawl delay is applied to the host.
+    /// Returns `None` if the robots.txt file can't be loaded, in which case the page should be loaded later.
+    async fn allowed_by_robots(&self, url: &str) -> Option<bool> {
+        if !self.config.robots_txt {
+            return Some(true);
+        }
+        let parsed = match Url::parse(url) {
+            Ok(parsed) => parsed,
+            Err(_) => return Some(true),
+        };
+        let host = url_to_host(url);
+
+        let rules = match self.robots.get(&host, now()) {
+            Some(rules) => rules,
+            None => {
+                // An interrupted page stays queued
+                self.shutdown.interruptible(self.rate_limiter.wait(&host)).await?;
+                let (_proxy, clients) = self.proxies.pick(&host)?;
+                match robots::fetch(&*clients.client, &parsed, &self.config.robots_agent).await {
+                    Some(rules) => {
+                        if let Some(crawl_delay) = rules.crawl_delay {
+                            self.rate_limiter.set_cooldown(&host, crawl_delay);
+                        }
+                        let rules = Arc::new(rules);
+                        self.robots.insert(host, Arc::clone(&rules), now());
+                        rules
+                    },
+                    // The host is unreachable: don't cache it so that it is tried again later
+                    None => return None,
+                }
             }
         };
 
-        // Blame the page if asked
-        if giveaway_urls.is_empty() && config.blame_useless_pages {
-            progress_bar.print_info("Useless", &format!("page loaded: {}", result), Color::Yellow, Style::Normal);
+        Some(rules.is_allowed(&parsed[Position::BeforePath..]))
+    }
+
+    /// Add the gleam links the short links of a page lead to, expanding the ones that are not cached yet.
+    /// Returns the errors that prevented some short links from being expanded.
+    async fn expand_short_links(&self, page: &mut SeenPage) -> Vec<String> {
+        let mut errors = Vec::new();
+        for short_link in &page.short_links {
+            let links = match self.shorteners.get(short_link) {
+                Some(links) => links,
+                None => {
+                    let expanded = self.with_retries(&url_to_host(short_link), move |clients| async move {
+                        shorteners::expand(&*clients.redirect_client, short_link).await
+                    }).await;
+                    match expanded {
+                        Ok(links) => {
+                            self.shorteners.insert(short_link.clone(), links.clone(), now());
+                            links
+                        },
+                        Err(Failure::Interrupted) => break,
+                        Err(e) => {
+                            errors.push(format!("when trying to expand {}: {}", short_link, e));
+                            continue;
+                        }
+                    }
+                }
+            };
+            for link in links {
+                if !page.links.contains(&link) {
+                    page.links.push(link);
+                }
+            }
+        }
+        errors
+    }
+
+    /// Load the queued result pages concurrently and queue the gleam links they contain.
+    /// The queue is saved every few pages and once they are all loaded, so that a crash only loads the last few pages again.
+    async fn load_results(&self, queue: &mut Queue, seen: &mut SeenStore, fast: bool) {
+        // Skip the pages visited recently and the blocked hosts
+        // The seeds are meant to be visited on every run, so they are never considered fresh
+        let mut results = Vec::new();
+        let mut skipped = 0;
+        let mut skipped_hosts = 0;
+        for result in queue.results.clone() {
+            let host = url_to_host(&result);
+            if !self.hosts.allows(&host, now()) {
+                queue.remove_result(&result);
+                skipped_hosts += 1;
+            } else if seen.is_fresh(&result, &host, now()) && !self.config.seeds.contains(&result) {
+                queue.remove_result(&result);
+                skipped += 1;
+            } else {
+                let previous = seen.get(&result).cloned();
+                results.push((result, previous));
+            }
+        }
+        if skipped > 0 || skipped_hosts > 0 {
+            queue.save();
         }
 
-        // Use the data
-        for gleam_link in giveaway_urls {
-            // Check if the url is valid and if we did not load this before
-            if let Some(key) = gleam::get_gleam_id(&gleam_link) {
-                if giveaways.contains_key(key) {
+        let mut progress_bar = ProgressBar::new(results.len());
+        progress_bar.set_action("Loading", Color::Blue, Style::Normal);
+        if skipped > 0 {
+            progress_bar.print_info("Skipped", &format!("{} pages visited recently", skipped), Color::White, Style::Normal);
+        }
+        if skipped_hosts > 0 {
+            progress_bar.print_info("Skipped", &format!("{} pages of blocked hosts", skipped_hosts), Color::White, Style::Normal);
+        }
+        let mut link_count = 0;
+        let mut blocked_count = 0;
+
+        let mut pages = stream::iter(results)
+            .take_while(|_| future::ready(!self.shutdown.requested()))
+            .map(move |(result, previous)| async move {
+                let allowed = match self.allowed_by_robots(&result).await {
+                    Some(allowed) => allowed,
+                    None => {
+                        let error = format!("robots.txt of {} is unavailable, {} is kept in the queue", url_to_host(&result), result);
+                        return (result, None, vec![error]);
+                    },
+                };
+                if !allowed {
+                    let page = SeenPage {
+                        visited_at: now(),
+                        etag: None,
+                        last_modified: None,
+                        links: Vec::new(),
+                        embedded: Vec::new(),
+                        short_links: Vec::new(),
+                        blocked: true,
+                    };
+                    return (result, Some(Ok(page)), Vec::new());
+                }
+                let (url, previous, redirectors) = (result.as_str(), previous.as_ref(), self.config.redirectors.as_slice());
+                let page = self.with_retries(&url_to_host(url), move |clients| async move {
+                    resolve(&*clients.client, url, previous, redirectors).await
+                }).await;
+                let mut page = match page {
+                    Err(Failure::Interrupted) => return (result, None, Vec::new()),
+                    page => page,
+                };
+                let errors = match &mut page {
+                    Ok(page) => self.expand_short_links(page).await,
+                    Err(_) => Vec::new(),
+                };
+                (result, Some(page), errors)
+            })
+            .buffer_unordered(self.config.concurrency);
+
+        while let Some((result, page, errors)) = pages.next().await {
+            for error in errors {
+                progress_bar.print_info("Error", &error, Color::Red, Style::Normal);
+            }
+            // The page was not loaded and stays in the queue
+            let page = match page {
+                Some(page) => page,
+                None => continue,
+            };
+            progress_bar.inc();
+            let (giveaway_urls, embedded_urls) = match page {
+                Ok(page) if page.blocked => {
+                    queue.remove_result(&result);
+                    progress_bar.print_info("Blocked", &format!("by robots.txt: {}", result), Color::Yellow, Style::Normal);
+                    blocked_count += 1;
+                    seen.insert(result.clone(), page);
+                    queue.checkpoint();
+                    continue;
+                },
+                Ok(page) => {
+                    queue.remove_result(&result);
+                    let urls = (page.links.clone(), page.embedded.clone());
+                    seen.insert(result.clone(), page);
+                    urls
+                },
+                // Retrying later would fail the same way
+                Err(Failure::Error(e)) if !e.is_transient() => {
+                    progress_bar.print_info("Dropped", &format!("{}: {}", result, e), Color::Red, Style::Normal);
+                    queue.remove_result(&result);
+                    queue.checkpoint();
+                    continue;
+                },
+                Err(Failure::Error(e)) => {
+                    if queue.record_failure(&result) > self.config.retry.max_requeues {
+                        progress_bar.print_info("Dropped", &format!("{} after repeated failures: {}", result, e), Color::Red, Style::Normal);
+                        queue.remove_result(&result);
+                    } else {
+                        progress_bar.print_info("Error", &format!("when trying to load {} (kept in the queue): {}", result, e), Color::Red, Style::Normal);
+                    }
+                    queue.checkpoint();
+                    continue;
+                },
+                Err(e) => {
+                    progress_bar.print_info("Skipped", &format!("{} (kept in the queue): {}", result, e), Color::Yellow, Style::Normal);
                     continue;
                 }
-            } else {
-                continue;
+            };
+
+            self.hosts.record_page(&url_to_host(&result), giveaway_urls.len());
+
+            // Blame the page if asked
+            if giveaway_urls.is_empty() && self.config.blame_useless_pages {
+                progress_bar.print_info("Useless", &format!("page loaded: {}", result), Color::Yellow, Style::Normal);
             }
 
-            if !fast {
-                let time_since_last_load = Instant::now() - last_gleam_request;
-                if time_since_last_load < Duration::from_secs(cooldown) {
-                    let time_to_sleep = Duration::from_secs(cooldown) - time_since_last_load;
-                    progress_bar.set_action("Sleeping", Color::Yellow, Style::Normal);
-                    sleep(time_to_sleep);
+            // Use the data
+            for gleam_link in giveaway_urls {
+                let embedded = embedded_urls.contains(&gleam_link);
+                let link = QueuedLink {
+                    url: gleam_link,
+                    referers: vec![Referer::new(result.clone(), now(), embedded)],
+                };
+                if fast {
+                    let kind = if embedded { "Embedded" } else { "Found" };
+                    progress_bar.print_info(kind, &link.url, Color::LightGreen, Style::Bold);
                 }
+                if queue.add_link(link) {
+                    link_count += 1;
+                }
+            }
+            queue.checkpoint();
+        }
+        progress_bar.set_action("Finished", Color::Green, Style::Bold);
+        queue.save();
+        seen.save(now());
+        self.shorteners.save(now());
+        self.hosts.save();
+        progress_bar.print_info("Finished", &format!("{} gleam links found, {} pages blocked by robots.txt", link_count, blocked_count), Color::Green, Style::Bold);
+        progress_bar.finalize();
+        println!();
+    }
 
-                progress_bar.set_action("Loading", Color::Blue, Style::Normal);
-                if let Ok(giveaway) = gleam::fetch(&gleam_link) {
-                    last_gleam_request = Instant::now();
-                    progress_bar.print_info("Found", &format!("{} {:>8} entries - {}", giveaway.get_url(), if let Some(entry_count) = giveaway.entry_count { entry_count.to_string() } else {String::from("unknow")}, giveaway.get_name()), Color::LightGreen, Style::Bold);
-                    outdated_meilisearch.push(giveaway.giveaway.campaign.key.clone());
-                    giveaways.insert(giveaway.giveaway.campaign.key.clone(), giveaway);
-                    
+    /// Fetch the queued gleam links and add the giveaways to the database.
+    /// Links which could not be loaded because of a network error are kept in the queue.
+    async fn load_queue(&self, queue: &mut Queue, database: &mut dyn Database, outdated_meilisearch: &mut Vec<String>) {
+        let links = queue.links.clone();
+        let mut progress_bar = ProgressBar::new(links.len());
+        let mut loaded = 0;
+        progress_bar.set_action("Loading", Color::Blue, Style::Normal);
+
+        let mut fetched = stream::iter(links)
+            .take_while(|_| future::ready(!self.shutdown.requested()))
+            .map(move |link| async move {
+                let url = link.url.as_str();
+                let giveaway = self.with_retries(gleam::GLEAM_HOST, move |clients| async move {
+                    gleam::fetch(&*clients.client, url).await
+                }).await;
+                match giveaway {
+                    Err(Failure::Interrupted) => (link, None),
+                    giveaway => (link, Some(giveaway)),
                 }
-            } else {
-                progress_bar.print_info("Found", &gleam_link, Color::LightGreen, Style::Bold);
+            })
+            .buffer_unordered(self.config.concurrency);
+
+        while let Some((link, giveaway)) = fetched.next().await {
+            let giveaway = match giveaway {
+                Some(giveaway) => giveaway,
+                None => continue,
+            };
+            progress_bar.inc();
+            match giveaway {
+                Ok(giveaway) => {
+                    progress_bar.print_info("Found", &format!("{} {:>8} entries - {}", giveaway.get_url(), if let Some(entry_count) = giveaway.entry_count { entry_count.to_string() } else {String::from("unknow")}, giveaway.get_name()), Color::LightGreen, Style::Bold);
+                    let key = giveaway.giveaway.campaign.key.clone();
+                    outdated_meilisearch.push(key.clone());
+                    if database.get(&key).is_none() {
+                        let mut hosts = link.referers.iter().map(|referer| referer.host.as_str()).collect::<Vec<_>>();
+                        hosts.sort_unstable();
+                        hosts.dedup();
+                        for host in hosts {
+                            self.hosts.record_giveaway(host);
+                        }
+                    }
+                    database.merge(giveaway);
+                    for referer in link.referers.iter().cloned() {
+                        database.add_referer(&key, referer);
+                    }
+                    loaded += 1;
+                },
+                Err(Failure::Error(gleam::Error::NetworkError(e))) => {
+                    if queue.record_failure(&link.url) <= self.config.retry.max_requeues {
+                        progress_bar.print_info("Timeout", &format!("Failed to load {} (kept in the queue): {}", link.url, e), Color::Yellow, Style::Bold);
+                        queue.checkpoint();
+                        continue;
+                    }
+                    progress_bar.print_info("Dropped", &format!("{} after repeated failures: {}", link.url, e), Color::Red, Style::Normal);
+                },
+                Err(Failure::Error(e)) => {
+                    progress_bar.print_info("Invalid", &format!("giveaway {}: {:?}", link.url, e), Color::Red, Style::Blink);
+                },
+                Err(e) => {
+                    progress_bar.print_info("Skipped", &format!("{} (kept in the queue): {}", link.url, e), Color::Yellow, Style::Bold);
+                    continue;
+                },
             }
+
+            // Save the giveaway before forgetting the link
+            database.save();
+            queue.remove_link(&link.url);
+            queue.checkpoint();
         }
-        
-        progress_bar.inc();
-        timeout_check.insert(url_to_host(result), Instant::now());
+        queue.save();
+        progress_bar.set_action("Finished", Color::Green, Style::Bold);
+        progress_bar.print_info("Finished", &format!("{} giveaways found", loaded), Color::Green, Style::Bold);
+        progress_bar.finalize();
+        println!();
     }
-    progress_bar.set_action("Finished", Color::Green, Style::Bold);
-    progress_bar.print_info("Finished", &format!("{} giveaways found", giveaways.len()), Color::Green, Style::Bold);
-    progress_bar.finalize();
-    println!();
-}
 
-fn update_giveaways(to_update: Vec<String>, giveaways: &mut HashMap<String, SearchResult>, outdated_meilisearch: &mut Vec<String>, cooldown: u64) {
-    let len = to_update.len();
-    let mut progress_bar = ProgressBar::new(len);
-    for key in to_update {
+    async fn update_giveaways(&self, to_update: Vec<SearchResult>, database: &mut dyn Database, outdated_meilisearch: &mut Vec<String>) {
+        let len = to_update.len();
+        let mut progress_bar = ProgressBar::new(len);
+        let mut updated_count = 0;
         progress_bar.set_action("Updating", Color::Blue, Style::Normal);
-        let mut old_giveaway = giveaways.remove(&key).unwrap();
-        outdated_meilisearch.push(key.clone());
 
-        match gleam::fetch(&old_giveaway.get_url()) {
-            Ok(updated) => {
-                let giveaway = old_giveaway + updated;
-                giveaways.insert(key, giveaway);
-            },
-            Err(gleam::Error::ParseError(ParseError::GiveawayJsonNotFound)) => {
-                progress_bar.print_info("Missing", &format!("giveaway {} -> removing", old_giveaway.get_url()), Color::Red, Style::Blink);
-            }
-            Err(gleam::Error::ParseError(e)) => {
-                progress_bar.print_info("Invalid", &format!("giveaway {}: {:?}", old_giveaway.get_url(), e), Color::Red, Style::Blink);
-                old_giveaway.last_updated = SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
-                giveaways.insert(key, old_giveaway);
-            }
-            Err(gleam::Error::NetworkError(_e)) => {
-                progress_bar.print_info("Timeout", "Failed to load giveaway (giveaway has not been updated)", Color::Yellow, Style::Bold);
-                giveaways.insert(key, old_giveaway);
-                sleep(Duration::from_secs(10));
-            }
-            Err(gleam::Error::InvalidGleamUrl) => {
-                progress_bar.print_info("Invalid", &format!("url {} (this code is almost unreachable)", old_giveaway.get_url()), Color::Red, Style::Blink);
+        let mut updated_giveaways = stream::iter(to_update)
+            .take_while(|_| future::ready(!self.shutdown.requested()))
+            .map(move |old_giveaway| async move {
+                let url = old_giveaway.get_url();
+                let url = url.as_str();
+                let updated = self.with_retries(gleam::GLEAM_HOST, move |clients| async move {
+                    gleam::fetch(&*clients.client, url).await
+                }).await;
+                match updated {
+                    Err(Failure::Interrupted) => (old_giveaway, None),
+                    updated => (old_giveaway, Some(updated)),
+                }
+            })
+            .buffer_unordered(self.config.concurrency);
+
+        while let Some((mut old_giveaway, updated)) = updated_giveaways.next().await {
+            let updated = match updated {
+                Some(updated) => updated,
+                None => continue,
+            };
+            let key = old_giveaway.giveaway.campaign.key.clone();
+            outdated_meilisearch.push(key.clone());
+
+            match updated {
+                Ok(updated) => {
+                    database.merge(updated);
+                },
+                Err(Failure::Error(gleam::Error::ParseError(ParseError::GiveawayJsonNotFound))) => {
+                    progress_bar.print_info("Missing", &format!("giveaway {} -> removing", old_giveaway.get_url()), Color::Red, Style::Blink);
+                    database.remove(&key);
+                }
+                Err(Failure::Error(gleam::Error::ParseError(e))) => {
+                    progress_bar.print_info("Invalid", &format!("giveaway {}: {:?}", old_giveaway.get_url(), e), Color::Red, Style::Blink);
+                    old_giveaway.last_updated = now();
+                    database.put(old_giveaway);
+                }
+                // The giveaway is still a candidate for the next refresh
+                Err(Failure::Error(gleam::Error::NetworkError(e))) => {
+                    progress_bar.print_info("Timeout", &format!("Failed to load giveaway {} (giveaway has not been updated): {}", old_giveaway.get_url(), e), Color::Yellow, Style::Bold);
+                }
+                Err(Failure::CircuitOpen) | Err(Failure::Interrupted) | Err(Failure::NoProxy) => {
+                    progress_bar.print_info("Skipped", &format!("giveaway {} (giveaway has not been updated)", old_giveaway.get_url()), Color::Yellow, Style::Bold);
+                }
+                Err(Failure::Error(gleam::Error::InvalidGleamUrl)) => {
+                    progress_bar.print_info("Invalid", &format!("url {} (this code is almost unreachable)", old_giveaway.get_url()), Color::Red, Style::Blink);
+                    database.remove(&key);
+                }
             }
+            updated_count += 1;
+            progress_bar.inc();
         }
-        progress_bar.set_action("Sleeping", Color::Yellow, Style::Normal);
-        progress_bar.inc();
-        sleep(Duration::from_secs(cooldown));
+        progress_bar.print_info("Finished", &format!("{} giveaways updated", updated_count), Color::Green, Style::Bold);
+        progress_bar.set_action("Finished", Color::Green, Style::Bold);
+        progress_bar.finalize();
+        println!();
     }
-    progress_bar.print_info("Finished", &format!("{} giveaways updated", len), Color::Green, Style::Bold);
-    progress_bar.set_action("Finished", Color::Green, Style::Bold);
-    progress_bar.finalize();
-    println!();
 }
 
 pub async fn launch(config: Config, fast: bool) {
-    std::env::set_var("MINREQ_TIMEOUT", config.timeout.to_string());
-    let cooldown = config.cooldown as u64;
+    let mut database = open_database(&config);
+    let mut queue = Queue::open(&config.queue_file);
+    let mut seen = SeenStore::open(&config.seen_file, config.seen_ttl, config.seen_ttl_per_host.clone());
+    let crawler = Crawler::new(config);
+    let config = &crawler.config;
+    if !queue.results.is_empty() || !queue.links.is_empty() {
+        println!("Resuming with {} result pages and {} gleam links in the queue", queue.results.len(), queue.links.len());
+    }
+
+    // Only search and resolve the results, keeping the links for a later run
+    if fast {
+        crawler.load_feeds(&mut queue).await;
+        crawler.load_seeds(&mut queue).await;
+        crawler.search_results(&mut queue).await;
+        crawler.load_results(&mut queue, &mut seen, fast).await;
+        return;
+    }
 
     if matches!(config.meilisearch.as_ref().map(|m| m.init_on_launch), Some(true)) {
         println!("Initializing the MeiliSearch index...");
-        init_meilisearch(&config).await;
+        init_meilisearch(database.as_ref(), config).await;
         println!("Done!");
     }
-    
-    loop {
-        let mut giveaways: HashMap<String, SearchResult> = HashMap::new();
-        let mut outdated_meilisearch = Vec::new();
-        let start = Instant::now();
-
-        // Search results on google
-        let results = search_google_results(cooldown);
-
-        // Load the results
-        load_results(results, &config, &mut giveaways, &mut outdated_meilisearch, fast);
-
-        if fast { break; }
-
-        // Read the database
-        read_database(&mut giveaways, &config);
-
-        // Select the oldest giveaways to update them
-        let mut to_update = Vec::new();
-        if config.update > 0 {
-            let mut giveaways = giveaways.iter().map(|(_i, g)| g).collect::<Vec<&SearchResult>>();
-            giveaways.sort_by_key(|g| g.last_updated);
-            for idx in 0..config.update {
-                if let Some(giveaway) = giveaways.get(idx) {
-                    to_update.push(giveaway.giveaway.campaign.key.clone())
-                }
+
+    let mut scheduler = Scheduler::new(&config.schedule, now());
+    let mut outdated_meilisearch = Vec::new();
+    while !crawler.shutdown.requested() {
+        let (task, run_at) = match scheduler.next() {
+            Some(next) => next,
+            None => {
+                println!("No task is scheduled anymore");
+                break;
+            },
+        };
+        let current_time = now();
+        if run_at > current_time {
+            crawler.shutdown.sleep(Duration::from_secs(run_at - current_time)).await;
+            if crawler.shutdown.requested() {
+                break;
             }
         }
-        
-        // Update the oldest giveaways
-        update_giveaways(to_update, &mut giveaways, &mut outdated_meilisearch, cooldown);
-
-        // Save the database
-        save_database(&giveaways, &config);
-
-        // Update meilisearch index
-        update_meilisearch(giveaways, &config, outdated_meilisearch).await;
-
-        if !fast {
-            let time_elapsed = Instant::now().duration_since(start);
-            let time_to_sleep = Duration::from_secs(3540) - time_elapsed;
-            sleep(time_to_sleep);
-        } else {
-            break;
+
+        crawler.retrier.new_cycle();
+        match task {
+            Task::Discovery => {
+                crawler.load_feeds(&mut queue).await;
+                crawler.load_seeds(&mut queue).await;
+                crawler.search_results(&mut queue).await;
+            },
+            Task::Resolving => {
+                crawler.load_results(&mut queue, &mut seen, false).await;
+                crawler.load_queue(&mut queue, database.as_mut(), &mut outdated_meilisearch).await;
+                for (host, until) in crawler.hosts.update_blocklist(now()) {
+                    println!("Blocked the host {} for {} hours because of its low yield", host, until.saturating_sub(now()) / 3600);
+                }
+                crawler.hosts.save();
+            },
+            Task::Refresh => {
+                let to_update = select_giveaways_to_refresh(database.refresh_candidates(now()), config.update, now());
+                crawler.update_giveaways(to_update, database.as_mut(), &mut outdated_meilisearch).await;
+                database.save();
+            },
+            Task::MeiliSearchSync => {
+                update_meilisearch(database.as_ref(), config, std::mem::take(&mut outdated_meilisearch)).await;
+            },
+            Task::Backup => {
+                if config.backup.automatic {
+                    database.compact();
+                    if let Err(e) = backup(config, &config.backup) {
+                        eprintln!("Failed to backup the database: {}", e);
+                    }
+                }
+            },
         }
-    }
-}
 
-/// put an url+noise, get url (without http://domain.something/)
-fn get_url(url: &str) -> &str {
-    let mut i = 0;
-    for c in url.chars() {
-        if !c.is_ascii_alphanumeric() && c != '-' && c != '/' && c != '_' {
-            break;
+        for evicted in crawler.proxies.take_evicted() {
+            println!("Evicted the proxy {}", evicted);
         }
-        i += 1;
+        scheduler.finished(task, now());
     }
-    &url[..i]
+
+    // Save everything before exiting
+    println!("Saving the database...");
+    database.save();
+    queue.save();
+    if !outdated_meilisearch.is_empty() {
+        println!("Updating the MeiliSearch index...");
+        update_meilisearch(database.as_ref(), config, outdated_meilisearch).await;
+    }
+    println!("Stopped");
 }
 
-pub fn resolve(url: &str) -> Result<Vec<String>, minreq::Error> {
-    use string_tools::*;
-
-    let response = match minreq::get(url)
-        .with_header("Accept", "text/html,text/plain")
-        .with_header(
-            "User-Agent",
-            "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:78.0) Gecko/20100101 Firefox/78.0",
-        )
-        .with_header(
-            "Host",
-            get_all_between(url, "://", "/"),
-        )
-        .send()
-    {
-        Ok(response) => response,
-        Err(e) => return Err(e),
-    };
-
-    let mut body = match response.as_str() {
-        Ok(body) => body,
-        Err(e) => return Err(e),
-    };
-
-    let mut rep = Vec::new();
-    while get_all_after(&body, "https://gleam.io/") != "" {
-        let url = get_url(get_all_after(&body, "https://gleam.io/"));
-        body = get_all_after(&body, "https://gleam.io/");
-        let url = if url.len() >= 20 {
-            format!("https://gleam.io/{}", &url[..20])
-        } else if !url.is_empty() {
-            format!("https://gleam.io/{}", url)
-        } else {
-            continue;
-        };
-        if !rep.contains(&url) {
-            rep.push(url);
+/// Fetch the links queued by fast runs and add them to the database.
+pub async fn ingest(config: Config, queue_file: Option<&str>) {
+    let mut queue = Queue::open(queue_file.unwrap_or(&config.queue_file));
+    let mut database = open_database(&config);
+    let crawler = Crawler::new(config);
+    let mut outdated_meilisearch = Vec::new();
+
+    crawler.load_queue(&mut queue, database.as_mut(), &mut outdated_meilisearch).await;
+    database.save();
+    crawler.hosts.save();
+
+    update_meilisearch(database.as_ref(), &crawler.config, outdated_meilisearch).await;
+}
+
+/// Load a page and extract the gleam links it contains.
+/// If the page has been visited before, it is loaded with a conditional request and the known links are reused if it did not change.
+/// Links to URL shorteners and `redirectors` are kept so that they can be expanded.
+pub async fn resolve(client: &dyn HttpClient, url: &str, previous: Option<&SeenPage>, redirectors: &[String]) -> Result<SeenPage, http::Error> {
+    let mut headers = vec![
+        ("Accept", "text/html,text/plain"),
+        ("User-Agent", "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:78.0) Gecko/20100101 Firefox/78.0"),
+    ];
+    if let Some(previous) = previous {
+        if let Some(etag) = &previous.etag {
+            headers.push(("If-None-Match", etag.as_str()));
         }
-    }
-    let mut final_rep = Vec::new();
-    for url in rep {
-        if let Some(id) = crate::gleam::get_gleam_id(&url) {
-            final_rep.push(format!("https://gleam.io/{}/-", id));
+        if let Some(last_modified) = &previous.last_modified {
+            headers.push(("If-Modified-Since", last_modified.as_str()));
         }
     }
-    Ok(final_rep)
+
+    let response = client.get(url, &headers).await?;
+
+    let etag = response.header("ETag").map(|value| value.to_string());
+    let last_modified = response.header("Last-Modified").map(|value| value.to_string());
+    if let (Some(previous), 304) = (previous, response.status) {
+        return Ok(SeenPage {
+            visited_at: now(),
+            etag: etag.or_else(|| previous.etag.clone()),
+            last_modified: last_modified.or_else(|| previous.last_modified.clone()),
+            links: previous.links.clone(),
+            embedded: previous.embedded.clone(),
+            short_links: previous.short_links.clone(),
+            blocked: false,
+        });
+    }
+    let body = response.error_for_status()?.body;
+
+    Ok(SeenPage {
+        visited_at: now(),
+        etag,
+        last_modified,
+        links: extract_gleam_links(&body),
+        embedded: extract_embedded_gleam_links(&body),
+        short_links: shorteners::short_links(extract_urls(&body), redirectors),
+        blocked: false,
+    })
 }
 
 #[cfg(test)]
 mod test {
     use super::resolve;
+    use crate::http::fake::FakeClient;
 
-    #[test]
-    fn resolving() {
-        assert_eq!(resolve("https://www.youtube.com/watch?v=-DS1qgHjoJY").unwrap().len(), 1);
-        assert_eq!(resolve("https://news.nestia.com/detail/Oculus-Quest-2---Infinite-Free-Games!/5222508").unwrap().len(), 1);
+    #[tokio::test]
+    async fn resolving() {
+        let anchors = std::fs::read_to_string("fixtures/extract/anchors.html").unwrap();
+        let client = FakeClient::new()
+            .page("https://example.com/giveaways", 200, &anchors)
+            .header("https://example.com/giveaways", "ETag", "\"v1\"")
+            .page("https://example.com/unchanged", 304, "")
+            .page("https://example.com/missing", 404, "Not found");
+
+        let page = resolve(&client, "https://example.com/giveaways", None, &[]).await.unwrap();
+        assert!(!page.links.is_empty());
+        assert_eq!(page.etag.as_deref(), Some("\"v1\""));
+
+        // A page which did not change is not parsed again
+        let unchanged = resolve(&client, "https://example.com/unchanged", Some(&page), &[]).await.unwrap();
+        assert_eq!(unchanged.links, page.links);
+        assert_eq!(unchanged.etag, page.etag);
+        let (_url, headers) = client.requests().pop().unwrap();
+        assert!(headers.contains(&("If-None-Match".to_string(), "\"v1\"".to_string())));
+
+        assert!(resolve(&client, "https://example.com/missing", None, &[]).await.is_err());
+        assert!(resolve(&client, "https://example.com/offline", None, &[]).await.is_err());
     }
-}
\ No newline at end of file
+}
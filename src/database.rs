@@ -1,33 +1,112 @@
-use std::{collections::HashMap, fs::File, io::prelude::*};
 use format::giveaway::SearchResult;
-use crate::config::Config;
-
-pub(crate) fn read_database(giveaways: &mut HashMap<String, SearchResult>, config: &Config) {
-    match File::open(&config.database_file) {
-        Ok(mut file) => {
-            let mut content = String::new();
-            file.read_to_string(&mut content).expect("Failed to read database");
-            let saved_giveaways = serde_json::from_str::<Vec<SearchResult>>(&content).expect("Failed to parse database");
-            for saved_giveaway in saved_giveaways {
-                match giveaways.remove(&saved_giveaway.giveaway.campaign.key) {
-                    Some(old_giveaway) => {
-                        let giveaway = saved_giveaway + old_giveaway;
-                        giveaways.insert(giveaway.giveaway.campaign.key.clone(), giveaway);
-                    },
-                    None => {
-                        giveaways.insert(saved_giveaway.giveaway.campaign.key.clone(), saved_giveaway);
+use serde::{Serialize, Deserialize};
+use crate::{config::{Config, DatabaseBackend}, util::url_to_host};
+
+mod json;
+mod sqlite;
+mod migrations;
+mod journal;
+pub(crate) use json::JsonDatabase;
+pub(crate) use sqlite::SqliteDatabase;
+
+/// A page linking to a giveaway.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Referer {
+    pub(crate) url: String,
+    pub(crate) host: String,
+    pub(crate) first_seen: u64,
+    pub(crate) last_seen: u64,
+    /// Whether the page embeds the giveaway in a widget rather than just linking to it
+    #[serde(default)]
+    pub(crate) embedded: bool,
+}
+
+impl Referer {
+    pub(crate) fn new(url: String, timestamp: u64, embedded: bool) -> Referer {
+        Referer {
+            host: url_to_host(&url),
+            url,
+            first_seen: timestamp,
+            last_seen: timestamp,
+            embedded,
+        }
+    }
+
+    /// Add the referers of `other` to `referers`, extending the dates of the known ones.
+    pub(crate) fn merge_into(referers: &mut Vec<Referer>, other: Referer) {
+        match referers.iter_mut().find(|referer| referer.url == other.url) {
+            Some(referer) => {
+                referer.first_seen = referer.first_seen.min(other.first_seen);
+                referer.last_seen = referer.last_seen.max(other.last_seen);
+                referer.embedded |= other.embedded;
+            },
+            None => referers.push(other),
+        }
+    }
+}
+
+/// A storage for the giveaways, indexed by campaign key.
+pub(crate) trait Database {
+    fn get(&self, key: &str) -> Option<SearchResult>;
+
+    /// Insert a giveaway, overwriting any stored giveaway with the same key.
+    fn put(&mut self, giveaway: SearchResult);
+
+    /// Remove a giveaway and its referers.
+    fn remove(&mut self, key: &str) -> Option<SearchResult>;
+
+    /// Get the pages a giveaway has been found on.
+    fn referers(&self, key: &str) -> Vec<Referer>;
+
+    /// Record a page a giveaway has been found on.
+    fn add_referer(&mut self, key: &str, referer: Referer);
+
+    /// Count the giveaways found on each host, sorted by decreasing count.
+    fn referer_hosts(&self) -> Vec<(String, usize)>;
+
+    /// Get the giveaways which may need to be refreshed:
+    /// the running ones and the ended ones which have not been refreshed since they ended.
+    fn refresh_candidates(&self, timestamp: u64) -> Vec<SearchResult>;
+
+    fn all(&self) -> Vec<SearchResult>;
+
+    fn count(&self) -> usize;
+
+    /// Count the giveaways ending after `timestamp`.
+    fn count_running(&self, timestamp: u64) -> usize;
+
+    /// Persist the pending changes.
+    fn save(&mut self);
+
+    /// Persist the whole database in a single file, so that it can be copied.
+    fn compact(&mut self) {
+        self.save();
+    }
+
+    /// Insert a giveaway, merging it with the stored giveaway if there is one.
+    /// The referers of both giveaways are kept.
+    fn merge(&mut self, giveaway: SearchResult) {
+        let giveaway = match self.get(&giveaway.giveaway.campaign.key) {
+            Some(stored) => {
+                let mut referers = stored.referers.clone();
+                for referer in &giveaway.referers {
+                    if !referers.contains(referer) {
+                        referers.push(referer.clone());
                     }
                 }
-            }
-        },
-        Err(e) => {
-            eprintln!("Can't open save file: {}", e);
-        }
+                let mut giveaway = stored + giveaway;
+                giveaway.referers = referers;
+                giveaway
+            },
+            None => giveaway,
+        };
+        self.put(giveaway);
     }
 }
 
-pub(crate) fn save_database(giveaways: &HashMap<String, SearchResult>, config: &Config) {
-    let mut file = File::create(&config.database_file).expect("Can't open database file");
-    let data = serde_json::to_string(&giveaways.values().collect::<Vec<&SearchResult>>()).expect("Can't serialize database");
-    file.write_all(data.as_bytes()).expect("Can't write data to database");
-}
\ No newline at end of file
+pub(crate) fn open_database(config: &Config) -> Box<dyn Database> {
+    match config.database_backend {
+        DatabaseBackend::Json => Box::new(JsonDatabase::open(&config.database_file, config.journal_compaction, config.archive_journal)),
+        DatabaseBackend::Sqlite => Box::new(SqliteDatabase::open(&config.database_file)),
+    }
+}
@@ -0,0 +1,214 @@
+use std::{collections::HashMap, fs::{self, OpenOptions}, io::{self, prelude::*}, time::SystemTime};
+use format::giveaway::SearchResult;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use super::{Referer, migrations::{CURRENT_VERSION, upgrade_giveaway}};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "operation", rename_all = "lowercase")]
+enum Change {
+    Insert { giveaway: Value },
+    Update { giveaway: Value },
+    Remove,
+    Referer { referer: Referer },
+}
+
+/// A line of the journal.
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    timestamp: u64,
+    version: u64,
+    key: String,
+    #[serde(flatten)]
+    change: Change,
+}
+
+/// An append-only log of the changes made to the giveaways since the last snapshot, stored in JSON lines.
+pub(crate) struct Journal {
+    path: String,
+    pending: Vec<Entry>,
+    len: usize,
+}
+
+impl Journal {
+    pub(crate) fn open(path: String) -> Journal {
+        Journal {
+            path,
+            pending: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Apply the changes stored in the journal file.
+    /// An incomplete last line, left by a process killed while writing it, is removed from the file so that the next entries start on a new line.
+    pub(crate) fn replay(&mut self, giveaways: &mut HashMap<String, SearchResult>, referers: &mut HashMap<String, Vec<Referer>>) -> Result<(), String> {
+        let content = match fs::read(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let complete = content.iter().rposition(|byte| *byte == b'\n').map(|end| end + 1).unwrap_or(0);
+        if complete < content.len() {
+            eprintln!("Removing the incomplete last line of the journal {}", self.path);
+            let file = OpenOptions::new().write(true).open(&self.path).map_err(|e| e.to_string())?;
+            file.set_len(complete as u64).map_err(|e| e.to_string())?;
+            file.sync_data().map_err(|e| e.to_string())?;
+        }
+
+        for (idx, line) in content[..complete].split(|byte| *byte == b'\n').enumerate().filter(|(_idx, line)| !line.is_empty()) {
+            let entry: Entry = serde_json::from_slice(line).map_err(|e| format!("invalid journal entry at line {}: {}", idx + 1, e))?;
+
+            match entry.change {
+                Change::Insert { mut giveaway } | Change::Update { mut giveaway } => {
+                    for referer in upgrade_giveaway(entry.version, &mut giveaway)? {
+                        Referer::merge_into(referers.entry(entry.key.clone()).or_default(), referer);
+                    }
+                    let giveaway = serde_json::from_value(giveaway).map_err(|e| e.to_string())?;
+                    giveaways.insert(entry.key, giveaway);
+                },
+                Change::Remove => {
+                    giveaways.remove(&entry.key);
+                    referers.remove(&entry.key);
+                },
+                Change::Referer { referer } => {
+                    Referer::merge_into(referers.entry(entry.key).or_default(), referer);
+                },
+            }
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, key: &str, change: Change) {
+        self.pending.push(Entry {
+            timestamp: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs(),
+            version: CURRENT_VERSION,
+            key: key.to_string(),
+            change,
+        });
+    }
+
+    pub(crate) fn record_put(&mut self, giveaway: &SearchResult, existed: bool) {
+        let value = serde_json::to_value(giveaway).expect("Can't serialize giveaway");
+        let change = if existed {
+            Change::Update { giveaway: value }
+        } else {
+            Change::Insert { giveaway: value }
+        };
+        self.record(&giveaway.giveaway.campaign.key, change);
+    }
+
+    pub(crate) fn record_remove(&mut self, key: &str) {
+        self.record(key, Change::Remove);
+    }
+
+    pub(crate) fn record_referer(&mut self, key: &str, referer: &Referer) {
+        self.record(key, Change::Referer { referer: referer.clone() });
+    }
+
+    /// Number of entries written in the journal file.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Append the pending changes to the journal file.
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut data = String::new();
+        for entry in &self.pending {
+            data.push_str(&serde_json::to_string(entry).expect("Can't serialize journal entry"));
+            data.push('\n');
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(data.as_bytes())?;
+        file.sync_data()?;
+
+        self.len += self.pending.len();
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Empty the journal once its changes are part of a snapshot.
+    /// The journal file is renamed with a timestamp instead of being deleted if `archive` is true.
+    pub(crate) fn clear(&mut self, archive: bool) -> io::Result<()> {
+        let result = if archive {
+            let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
+            fs::rename(&self.path, format!("{}-{}", self.path, timestamp))
+        } else {
+            fs::remove_file(&self.path)
+        };
+        self.len = 0;
+        match result {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn giveaway(key: &str) -> SearchResult {
+        let content = fs::read_to_string("new_giveaways.json").unwrap();
+        let mut giveaway = serde_json::from_str::<Vec<SearchResult>>(&content).unwrap().remove(0);
+        giveaway.giveaway.campaign.key = key.to_string();
+        giveaway
+    }
+
+    fn replay(path: &str) -> Result<HashMap<String, SearchResult>, String> {
+        let mut giveaways = HashMap::new();
+        Journal::open(path.to_string()).replay(&mut giveaways, &mut HashMap::new())?;
+        Ok(giveaways)
+    }
+
+    #[test]
+    fn torn_write() {
+        let path = std::env::temp_dir().join(format!("journal-torn-{}.journal", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut journal = Journal::open(path.to_string());
+        journal.record_put(&giveaway("aaaaa"), false);
+        journal.record_put(&giveaway("bbbbb"), false);
+        journal.flush().unwrap();
+
+        // The process is killed while writing an entry
+        let complete_length = fs::metadata(path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(br#"{"timestamp":1605394799,"version":3,"key":"cc"#).unwrap();
+        assert_eq!(replay(path).unwrap().len(), 2);
+        assert_eq!(fs::metadata(path).unwrap().len(), complete_length);
+
+        // The next entries are appended on their own line
+        let mut journal = Journal::open(path.to_string());
+        journal.replay(&mut HashMap::new(), &mut HashMap::new()).unwrap();
+        journal.record_put(&giveaway("ccccc"), false);
+        journal.record_remove("aaaaa");
+        journal.flush().unwrap();
+        let giveaways = replay(path).unwrap();
+        fs::remove_file(path).unwrap();
+        let mut keys = giveaways.keys().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, vec!["bbbbb", "ccccc"]);
+    }
+
+    #[test]
+    fn corrupted_entry() {
+        let path = std::env::temp_dir().join(format!("journal-corrupted-{}.journal", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut journal = Journal::open(path.to_string());
+        journal.record_put(&giveaway("aaaaa"), false);
+        journal.flush().unwrap();
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(b"{\"timestamp\":\n").unwrap();
+        journal.record_put(&giveaway("bbbbb"), false);
+        journal.flush().unwrap();
+
+        let result = replay(path);
+        fs::remove_file(path).unwrap();
+        assert!(result.unwrap_err().starts_with("invalid journal entry at line 2"));
+    }
+}
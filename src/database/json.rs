@@ -0,0 +1,202 @@
+use std::{collections::HashMap, fs::File, io::prelude::*, process::exit};
+use format::giveaway::SearchResult;
+use serde::Serialize;
+use crate::util::atomic_write;
+use super::{Database, Referer, journal::Journal, migrations::{CURRENT_VERSION, upgrade_json_database}};
+
+/// The whole database loaded in memory.
+/// It is stored in a JSON snapshot and a journal of the changes made since the snapshot.
+/// The journal is compacted into a new snapshot when it becomes too long.
+pub(crate) struct JsonDatabase {
+    path: String,
+    giveaways: HashMap<String, SearchResult>,
+    referers: HashMap<String, Vec<Referer>>,
+    journal: Journal,
+    compact_after: usize,
+    archive_journal: bool,
+}
+
+#[derive(Serialize)]
+struct DatabaseFile<'a> {
+    version: u64,
+    giveaways: Vec<&'a SearchResult>,
+    referers: &'a HashMap<String, Vec<Referer>>,
+}
+
+impl JsonDatabase {
+    pub(crate) fn open(path: &str, compact_after: usize, archive_journal: bool) -> JsonDatabase {
+        let mut giveaways = HashMap::new();
+        let mut referers = HashMap::new();
+        match File::open(path) {
+            Ok(mut file) => {
+                let mut content = String::new();
+                file.read_to_string(&mut content).expect("Failed to read database");
+                let (saved_giveaways, saved_referers) = match JsonDatabase::parse(&content) {
+                    Ok(saved) => saved,
+                    Err(e) => {
+                        eprintln!("Failed to parse database {}: {}", path, e);
+                        exit(1);
+                    }
+                };
+                for saved_giveaway in saved_giveaways {
+                    giveaways.insert(saved_giveaway.giveaway.campaign.key.clone(), saved_giveaway);
+                }
+                referers = saved_referers;
+            },
+            Err(e) => {
+                eprintln!("Can't open save file: {}", e);
+            }
+        }
+
+        let mut journal = Journal::open(format!("{}.journal", path));
+        if let Err(e) = journal.replay(&mut giveaways, &mut referers) {
+            eprintln!("Failed to replay the journal of the database {}: {}", path, e);
+            exit(1);
+        }
+
+        JsonDatabase {
+            path: path.to_string(),
+            giveaways,
+            referers,
+            journal,
+            compact_after,
+            archive_journal,
+        }
+    }
+
+    /// Parse the content of a database file, migrating it to the current schema if needed.
+    #[allow(clippy::type_complexity)]
+    fn parse(content: &str) -> Result<(Vec<SearchResult>, HashMap<String, Vec<Referer>>), String> {
+        let (giveaways, referers) = upgrade_json_database(content)?;
+        let giveaways = giveaways
+            .into_iter()
+            .map(|giveaway| serde_json::from_value(giveaway).map_err(|e| e.to_string()))
+            .collect::<Result<Vec<SearchResult>, String>>()?;
+        Ok((giveaways, referers))
+    }
+
+    /// Check that a file is a valid database and return the number of giveaways it contains.
+    pub(crate) fn check(path: &str) -> Result<usize, String> {
+        let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Ok(JsonDatabase::parse(&content)?.0.len())
+    }
+}
+
+impl Database for JsonDatabase {
+    fn get(&self, key: &str) -> Option<SearchResult> {
+        self.giveaways.get(key).cloned()
+    }
+
+    fn put(&mut self, giveaway: SearchResult) {
+        let key = giveaway.giveaway.campaign.key.clone();
+        self.journal.record_put(&giveaway, self.giveaways.contains_key(&key));
+        self.giveaways.insert(key, giveaway);
+    }
+
+    fn remove(&mut self, key: &str) -> Option<SearchResult> {
+        let giveaway = self.giveaways.remove(key);
+        if giveaway.is_some() {
+            self.journal.record_remove(key);
+        }
+        self.referers.remove(key);
+        giveaway
+    }
+
+    fn referers(&self, key: &str) -> Vec<Referer> {
+        self.referers.get(key).cloned().unwrap_or_default()
+    }
+
+    fn add_referer(&mut self, key: &str, referer: Referer) {
+        self.journal.record_referer(key, &referer);
+        Referer::merge_into(self.referers.entry(key.to_string()).or_default(), referer);
+    }
+
+    fn referer_hosts(&self) -> Vec<(String, usize)> {
+        let mut hosts: HashMap<&str, usize> = HashMap::new();
+        for referers in self.referers.values() {
+            let mut giveaway_hosts = referers.iter().map(|r| r.host.as_str()).collect::<Vec<&str>>();
+            giveaway_hosts.sort_unstable();
+            giveaway_hosts.dedup();
+            for host in giveaway_hosts {
+                *hosts.entry(host).or_default() += 1;
+            }
+        }
+        let mut hosts = hosts.into_iter().map(|(host, count)| (host.to_string(), count)).collect::<Vec<(String, usize)>>();
+        hosts.sort_by(|(host1, count1), (host2, count2)| count2.cmp(count1).then(host1.cmp(host2)));
+        hosts
+    }
+
+    fn refresh_candidates(&self, timestamp: u64) -> Vec<SearchResult> {
+        self.giveaways.values().filter(|g| g.ends_at() > timestamp || g.last_updated <= g.ends_at()).cloned().collect()
+    }
+
+    fn all(&self) -> Vec<SearchResult> {
+        self.giveaways.values().cloned().collect()
+    }
+
+    fn count(&self) -> usize {
+        self.giveaways.len()
+    }
+
+    fn count_running(&self, timestamp: u64) -> usize {
+        self.giveaways.values().filter(|g| g.ends_at() > timestamp).count()
+    }
+
+    fn save(&mut self) {
+        self.journal.flush().expect("Can't write data to database journal");
+        if self.journal.len() >= self.compact_after {
+            self.compact();
+        }
+    }
+
+    fn compact(&mut self) {
+        self.journal.flush().expect("Can't write data to database journal");
+
+        let data = serde_json::to_string(&DatabaseFile {
+            version: CURRENT_VERSION,
+            giveaways: self.giveaways.values().collect(),
+            referers: &self.referers,
+        }).expect("Can't serialize database");
+        atomic_write(&self.path, data.as_bytes()).expect("Can't write data to database");
+
+        // Replaying the journal on the new snapshot is harmless, so a crash before this point loses nothing
+        self.journal.clear(self.archive_journal).expect("Can't clear database journal");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn giveaway(key: &str) -> SearchResult {
+        let content = std::fs::read_to_string("new_giveaways.json").unwrap();
+        let mut giveaway = serde_json::from_str::<Vec<SearchResult>>(&content).unwrap().remove(0);
+        giveaway.giveaway.campaign.key = key.to_string();
+        giveaway
+    }
+
+    #[test]
+    fn crash_before_clearing_the_journal() {
+        let path = std::env::temp_dir().join(format!("database-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let journal_path = format!("{}.journal", path);
+        let mut database = JsonDatabase::open(path, 1000, false);
+        database.put(giveaway("aaaaa"));
+        database.put(giveaway("bbbbb"));
+        database.add_referer("aaaaa", Referer::new(String::from("https://example.com/giveaways"), 10, false));
+        database.remove("bbbbb");
+        database.save();
+
+        // The snapshot is written but the process is killed before the journal is cleared
+        let journal = std::fs::read(&journal_path).unwrap();
+        database.compact();
+        assert!(std::fs::metadata(&journal_path).is_err());
+        std::fs::write(&journal_path, journal).unwrap();
+
+        let database = JsonDatabase::open(path, 1000, false);
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(&journal_path).unwrap();
+        assert_eq!(database.all().iter().map(|g| g.giveaway.campaign.key.as_str()).collect::<Vec<_>>(), vec!["aaaaa"]);
+        assert_eq!(database.referers("aaaaa"), vec![Referer::new(String::from("https://example.com/giveaways"), 10, false)]);
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use serde_json::{json, Value};
+use crate::{gleam::GLEAM_HOST, util::url_to_host};
+use super::Referer;
+
+/// The version of the database schema written by this version of the client.
+/// Increase it and add a step in [migrate_giveaway] when the serialized giveaways change.
+pub(crate) const CURRENT_VERSION: u64 = 4;
+
+/// Whether `url` is a page of gleam.io, such as the giveaway itself, rather than a page linking to the giveaway.
+fn is_self_referer(url: &str) -> bool {
+    let host = url_to_host(url);
+    host == GLEAM_HOST || host.ends_with(&format!(".{}", GLEAM_HOST))
+}
+
+/// Upgrade a serialized giveaway from `version` to `version + 1`.
+/// The referers moved out of the giveaway are added to `referers`.
+fn migrate_giveaway(version: u64, giveaway: &mut Value, referers: &mut Vec<Referer>) {
+    match version {
+        // Databases written before versioning may lack the fields added over time
+        0 => {
+            if let Some(giveaway) = giveaway.as_object_mut() {
+                giveaway.entry("referers").or_insert_with(|| json!([]));
+                giveaway.entry("last_updated").or_insert_with(|| json!(0));
+                giveaway.entry("entry_count").or_insert(Value::Null);
+                giveaway.entry("entry_evolution").or_insert(Value::Null);
+            }
+        },
+        // The referers with their dates are stored beside the giveaways.
+        // The giveaways found in fast mode were their own referer, and the date of a referer is unknown before then.
+        1 => {
+            if let Some(giveaway) = giveaway.as_object_mut() {
+                let last_updated = giveaway.get("last_updated").and_then(Value::as_u64).unwrap_or(0);
+                if let Some(Value::Array(urls)) = giveaway.get_mut("referers") {
+                    for url in urls.drain(..).filter_map(|url| url.as_str().map(str::to_string)) {
+                        if !is_self_referer(&url) {
+                            Referer::merge_into(referers, Referer::new(url, last_updated, false));
+                        }
+                    }
+                }
+            }
+        },
+        // The referers record whether they embed the giveaway, which doesn't change the giveaways either
+        2 => (),
+        // The referers were still copied into the giveaways although they were already stored beside them
+        3 => {
+            if let Some(Value::Array(urls)) = giveaway.get_mut("referers") {
+                urls.clear();
+            }
+        },
+        _ => unreachable!("No migration from the schema version {}", version),
+    }
+}
+
+pub(crate) fn check_version(version: u64) -> Result<(), String> {
+    if version > CURRENT_VERSION {
+        return Err(format!("the database has been written by a more recent client (schema version {}, this client supports up to {})", version, CURRENT_VERSION));
+    }
+    Ok(())
+}
+
+/// Upgrade a serialized giveaway from `version` to [CURRENT_VERSION].
+/// Returns the referers which were stored in the giveaway and must be stored beside it.
+pub(crate) fn upgrade_giveaway(version: u64, giveaway: &mut Value) -> Result<Vec<Referer>, String> {
+    check_version(version)?;
+    let mut referers = Vec::new();
+    for version in version..CURRENT_VERSION {
+        migrate_giveaway(version, giveaway, &mut referers);
+    }
+    Ok(referers)
+}
+
+/// Read the content of a JSON database of any version and return the serialized giveaways upgraded to [CURRENT_VERSION],
+/// with the referers of each giveaway.
+#[allow(clippy::type_complexity)]
+pub(crate) fn upgrade_json_database(content: &str) -> Result<(Vec<Value>, HashMap<String, Vec<Referer>>), String> {
+    let (version, mut giveaways, referers) = match serde_json::from_str(content).map_err(|e| e.to_string())? {
+        // Databases written before versioning are a bare array of giveaways
+        Value::Array(giveaways) => (0, giveaways, None),
+        Value::Object(mut database) => {
+            let version = database.get("version").and_then(|v| v.as_u64()).ok_or("missing schema version")?;
+            match database.remove("giveaways") {
+                Some(Value::Array(giveaways)) => (version, giveaways, database.remove("referers")),
+                _ => return Err(String::from("missing giveaways")),
+            }
+        },
+        _ => return Err(String::from("unexpected database content")),
+    };
+
+    check_version(version)?;
+    let mut referers: HashMap<String, Vec<Referer>> = match referers {
+        Some(referers) => serde_json::from_value(referers).map_err(|e| e.to_string())?,
+        None => HashMap::new(),
+    };
+    for giveaway in &mut giveaways {
+        let moved_referers = upgrade_giveaway(version, giveaway)?;
+        if let Some(key) = giveaway.get("key").and_then(Value::as_str) {
+            for referer in moved_referers {
+                Referer::merge_into(referers.entry(key.to_string()).or_default(), referer);
+            }
+        }
+    }
+    Ok((giveaways, referers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upgrade_unversioned_database() {
+        let (giveaways, referers) = upgrade_json_database(r#"[{"giveaway":{},"referers":["https://gleam.io/abcde/-"],"last_updated":42,"entry_evolution":{"40":3}},{"giveaway":{}}]"#).unwrap();
+        assert_eq!(giveaways.len(), 2);
+        assert!(referers.is_empty());
+        assert_eq!(giveaways[0]["referers"], json!([]));
+        assert_eq!(giveaways[0]["last_updated"], json!(42));
+        assert_eq!(giveaways[0]["entry_evolution"], json!({"40": 3}));
+        assert_eq!(giveaways[1]["referers"], json!([]));
+        assert_eq!(giveaways[1]["last_updated"], json!(0));
+        assert_eq!(giveaways[1]["entry_evolution"], Value::Null);
+    }
+
+    #[test]
+    fn upgrade_versioned_database() {
+        let content = format!(r#"{{"version":{},"giveaways":[{{"giveaway":{{}},"last_updated":42}}],"referers":{{}}}}"#, CURRENT_VERSION);
+        let (giveaways, referers) = upgrade_json_database(&content).unwrap();
+        assert_eq!(giveaways, vec![json!({"giveaway": {}, "last_updated": 42})]);
+        assert!(referers.is_empty());
+
+        let (giveaways, _referers) = upgrade_json_database(r#"{"version":1,"giveaways":[{"last_updated":42}]}"#).unwrap();
+        assert_eq!(giveaways, vec![json!({"last_updated": 42})]);
+
+        let content = format!(r#"{{"version":{},"giveaways":[]}}"#, CURRENT_VERSION + 1);
+        assert!(upgrade_json_database(&content).is_err());
+        assert!(upgrade_json_database(r#"{"giveaways":[]}"#).is_err());
+        assert!(upgrade_json_database("42").is_err());
+    }
+
+    #[test]
+    fn move_referers() {
+        let content = r#"{"version":1,"giveaways":[{"key":"abcde","last_updated":42,"referers":["https://gleam.io/abcde/-","https://example.com/giveaways","https://embed.gleam.io/abcde"]}]}"#;
+        let (giveaways, referers) = upgrade_json_database(content).unwrap();
+        assert_eq!(giveaways[0]["referers"], json!([]));
+        assert_eq!(referers["abcde"], vec![Referer::new(String::from("https://example.com/giveaways"), 42, false)]);
+
+        // The referers stored beside the giveaways are kept
+        let content = r#"{"version":1,"giveaways":[{"key":"abcde","last_updated":42,"referers":["https://example.com/giveaways"]}],"referers":{"abcde":[{"url":"https://example.com/giveaways","host":"example.com","first_seen":10,"last_seen":20}]}}"#;
+        let (_giveaways, referers) = upgrade_json_database(content).unwrap();
+        assert_eq!(referers["abcde"].len(), 1);
+        assert_eq!((referers["abcde"][0].first_seen, referers["abcde"][0].last_seen), (10, 42));
+
+        // The copies left in the giveaways are dropped
+        let content = r#"{"version":3,"giveaways":[{"key":"abcde","last_updated":42,"referers":["https://example.com/giveaways"]}],"referers":{"abcde":[{"url":"https://example.com/giveaways","host":"example.com","first_seen":10,"last_seen":20,"embedded":true}]}}"#;
+        let (giveaways, referers) = upgrade_json_database(content).unwrap();
+        assert_eq!(giveaways[0]["referers"], json!([]));
+        assert_eq!((referers["abcde"][0].first_seen, referers["abcde"][0].last_seen, referers["abcde"][0].embedded), (10, 20, true));
+    }
+}
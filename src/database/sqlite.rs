@@ -0,0 +1,236 @@
+use std::process::exit;
+use format::giveaway::SearchResult;
+use rusqlite::{params, Connection, OptionalExtension};
+use super::{Database, Referer, migrations::{CURRENT_VERSION, check_version, upgrade_giveaway}};
+
+/// The giveaways stored in an SQLite database.
+/// Each giveaway is serialized in JSON and its key, update time and end time are indexed.
+/// Referers are stored in their own table.
+/// Changes are grouped in a transaction which is committed by [Database::save].
+/// The schema version is stored in the `user_version` pragma.
+pub(crate) struct SqliteDatabase {
+    connection: Connection,
+}
+
+impl SqliteDatabase {
+    pub(crate) fn open(path: &str) -> SqliteDatabase {
+        let connection = Connection::open(path).expect("Can't open database file");
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS giveaways (
+                key TEXT PRIMARY KEY NOT NULL,
+                last_updated INTEGER NOT NULL,
+                ends_at INTEGER NOT NULL,
+                data TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS giveaways_last_updated ON giveaways (last_updated);
+            CREATE INDEX IF NOT EXISTS giveaways_ends_at ON giveaways (ends_at);
+            CREATE TABLE IF NOT EXISTS referers (
+                key TEXT NOT NULL,
+                url TEXT NOT NULL,
+                host TEXT NOT NULL,
+                first_seen INTEGER NOT NULL,
+                last_seen INTEGER NOT NULL,
+                PRIMARY KEY (key, url)
+            );
+            CREATE INDEX IF NOT EXISTS referers_host ON referers (host);
+            BEGIN;",
+        ).expect("Failed to init database");
+
+        let database = SqliteDatabase {
+            connection,
+        };
+        if let Err(e) = database.migrate() {
+            eprintln!("Failed to migrate database {}: {}", path, e);
+            exit(1);
+        }
+        database
+    }
+
+    fn version(connection: &Connection) -> rusqlite::Result<u64> {
+        connection.query_row("PRAGMA user_version", params![], |row| row.get::<_, i64>(0)).map(|v| v as u64)
+    }
+
+    /// Upgrade the stored giveaways to the current schema.
+    fn migrate(&self) -> Result<(), String> {
+        let version = SqliteDatabase::version(&self.connection).map_err(|e| e.to_string())?;
+        // Even without giveaways to upgrade, a newer database must not be marked with an older version
+        check_version(version)?;
+        if version == CURRENT_VERSION {
+            return Ok(());
+        }
+
+        // The tables are created with their first schema, the columns added since are added here
+        if version < 3 {
+            self.connection.execute_batch("ALTER TABLE referers ADD COLUMN embedded INTEGER NOT NULL DEFAULT 0;").map_err(|e| e.to_string())?;
+        }
+
+        let mut statement = self.connection.prepare("SELECT key, data FROM giveaways").map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map(params![], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .map_err(|e| e.to_string())?
+            .collect::<rusqlite::Result<Vec<(String, String)>>>()
+            .map_err(|e| e.to_string())?;
+        for (key, data) in rows {
+            let mut giveaway = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+            for referer in upgrade_giveaway(version, &mut giveaway)? {
+                SqliteDatabase::insert_referer(&self.connection, &key, &referer).map_err(|e| e.to_string())?;
+            }
+            self.connection
+                .execute("UPDATE giveaways SET data = ? WHERE key = ?", params![giveaway.to_string(), key])
+                .map_err(|e| e.to_string())?;
+        }
+        self.connection.execute_batch(&format!("PRAGMA user_version = {}; COMMIT; BEGIN;", CURRENT_VERSION)).map_err(|e| e.to_string())
+    }
+
+    /// Insert a referer, extending the dates of the known one if there is one.
+    fn insert_referer(connection: &Connection, key: &str, referer: &Referer) -> rusqlite::Result<usize> {
+        connection.execute(
+            "INSERT INTO referers (key, url, host, first_seen, last_seen, embedded) VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT (key, url) DO UPDATE SET first_seen = min(first_seen, excluded.first_seen), last_seen = max(last_seen, excluded.last_seen), embedded = max(embedded, excluded.embedded)",
+            params![key, referer.url, referer.host, referer.first_seen as i64, referer.last_seen as i64, referer.embedded],
+        )
+    }
+
+    /// Check that a file is a valid database and return the number of giveaways it contains.
+    pub(crate) fn check(path: &str) -> Result<usize, String> {
+        let connection = Connection::open(path).map_err(|e| e.to_string())?;
+        let integrity = connection.query_row("PRAGMA integrity_check", params![], |row| row.get::<_, String>(0)).map_err(|e| e.to_string())?;
+        if integrity != "ok" {
+            return Err(integrity);
+        }
+        check_version(SqliteDatabase::version(&connection).map_err(|e| e.to_string())?)?;
+        let count = connection.query_row("SELECT COUNT(*) FROM giveaways", params![], |row| row.get::<_, i64>(0)).map_err(|e| e.to_string())?;
+        Ok(count as usize)
+    }
+
+    fn query(&self, sql: &str, params: &[&dyn rusqlite::ToSql]) -> Vec<SearchResult> {
+        let mut statement = self.connection.prepare_cached(sql).expect("Failed to prepare database query");
+        let rows = statement.query_map(params, |row| row.get::<_, String>(0)).expect("Failed to query database");
+        rows.map(|data| serde_json::from_str(&data.expect("Failed to read database")).expect("Failed to parse database"))
+            .collect()
+    }
+}
+
+impl Database for SqliteDatabase {
+    fn get(&self, key: &str) -> Option<SearchResult> {
+        self.connection
+            .query_row("SELECT data FROM giveaways WHERE key = ?", params![key], |row| row.get::<_, String>(0))
+            .optional()
+            .expect("Failed to query database")
+            .map(|data| serde_json::from_str(&data).expect("Failed to parse database"))
+    }
+
+    fn put(&mut self, giveaway: SearchResult) {
+        let data = serde_json::to_string(&giveaway).expect("Can't serialize giveaway");
+        self.connection.execute(
+            "INSERT OR REPLACE INTO giveaways (key, last_updated, ends_at, data) VALUES (?, ?, ?, ?)",
+            params![giveaway.giveaway.campaign.key, giveaway.last_updated as i64, giveaway.ends_at() as i64, data],
+        ).expect("Can't write data to database");
+    }
+
+    fn remove(&mut self, key: &str) -> Option<SearchResult> {
+        let giveaway = self.get(key);
+        self.connection.execute("DELETE FROM giveaways WHERE key = ?", params![key]).expect("Can't write data to database");
+        self.connection.execute("DELETE FROM referers WHERE key = ?", params![key]).expect("Can't write data to database");
+        giveaway
+    }
+
+    fn referers(&self, key: &str) -> Vec<Referer> {
+        let mut statement = self.connection
+            .prepare_cached("SELECT url, host, first_seen, last_seen, embedded FROM referers WHERE key = ? ORDER BY first_seen")
+            .expect("Failed to prepare database query");
+        let rows = statement.query_map(params![key], |row| Ok(Referer {
+            url: row.get(0)?,
+            host: row.get(1)?,
+            first_seen: row.get::<_, i64>(2)? as u64,
+            last_seen: row.get::<_, i64>(3)? as u64,
+            embedded: row.get(4)?,
+        })).expect("Failed to query database");
+        rows.map(|referer| referer.expect("Failed to read database")).collect()
+    }
+
+    fn add_referer(&mut self, key: &str, referer: Referer) {
+        SqliteDatabase::insert_referer(&self.connection, key, &referer).expect("Can't write data to database");
+    }
+
+    fn referer_hosts(&self) -> Vec<(String, usize)> {
+        let mut statement = self.connection
+            .prepare_cached("SELECT host, COUNT(DISTINCT key) AS count FROM referers GROUP BY host ORDER BY count DESC, host")
+            .expect("Failed to prepare database query");
+        let rows = statement.query_map(params![], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize))).expect("Failed to query database");
+        rows.map(|host| host.expect("Failed to read database")).collect()
+    }
+
+    fn refresh_candidates(&self, timestamp: u64) -> Vec<SearchResult> {
+        self.query("SELECT data FROM giveaways WHERE ends_at > ? OR last_updated <= ends_at", &[&(timestamp as i64)])
+    }
+
+    fn all(&self) -> Vec<SearchResult> {
+        self.query("SELECT data FROM giveaways", &[])
+    }
+
+    fn count(&self) -> usize {
+        self.connection
+            .query_row("SELECT COUNT(*) FROM giveaways", params![], |row| row.get::<_, i64>(0))
+            .expect("Failed to query database") as usize
+    }
+
+    fn count_running(&self, timestamp: u64) -> usize {
+        self.connection
+            .query_row("SELECT COUNT(*) FROM giveaways WHERE ends_at > ?", params![timestamp as i64], |row| row.get::<_, i64>(0))
+            .expect("Failed to query database") as usize
+    }
+
+    fn save(&mut self) {
+        self.connection.execute_batch("COMMIT; BEGIN;").expect("Can't write data to database");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn giveaway(key: &str) -> SearchResult {
+        let content = std::fs::read_to_string("new_giveaways.json").unwrap();
+        let mut giveaway = serde_json::from_str::<Vec<SearchResult>>(&content).unwrap().remove(0);
+        giveaway.giveaway.campaign.key = key.to_string();
+        giveaway
+    }
+
+    #[test]
+    fn storage() {
+        let mut database = SqliteDatabase::open(":memory:");
+        assert_eq!(SqliteDatabase::version(&database.connection).unwrap(), CURRENT_VERSION);
+        database.put(giveaway("aaaaa"));
+        database.put(giveaway("bbbbb"));
+        database.add_referer("aaaaa", Referer::new(String::from("https://example.com/giveaways"), 10, false));
+        database.add_referer("aaaaa", Referer::new(String::from("https://example.com/giveaways"), 20, true));
+        database.remove("bbbbb");
+        database.save();
+
+        assert_eq!(database.count(), 1);
+        assert_eq!(database.get("aaaaa"), Some(giveaway("aaaaa")));
+        assert_eq!(database.get("bbbbb"), None);
+        let mut referer = Referer::new(String::from("https://example.com/giveaways"), 10, true);
+        referer.last_seen = 20;
+        assert_eq!(database.referers("aaaaa"), vec![referer]);
+    }
+
+    #[test]
+    fn newer_version() {
+        // A database written by a more recent client, with referers but no giveaway to upgrade
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(&format!(
+            "CREATE TABLE giveaways (key TEXT PRIMARY KEY NOT NULL, last_updated INTEGER NOT NULL, ends_at INTEGER NOT NULL, data TEXT NOT NULL);
+            CREATE TABLE referers (key TEXT NOT NULL, url TEXT NOT NULL, host TEXT NOT NULL, first_seen INTEGER NOT NULL, last_seen INTEGER NOT NULL, embedded INTEGER NOT NULL DEFAULT 0, PRIMARY KEY (key, url));
+            INSERT INTO referers VALUES ('aaaaa', 'https://example.com/', 'example.com', 10, 10, 0);
+            PRAGMA user_version = {};
+            BEGIN;",
+            CURRENT_VERSION + 1,
+        )).unwrap();
+
+        let database = SqliteDatabase { connection };
+        assert!(database.migrate().unwrap_err().contains("more recent client"));
+        assert_eq!(SqliteDatabase::version(&database.connection).unwrap(), CURRENT_VERSION + 1);
+    }
+}
@@ -0,0 +1,152 @@
+use std::{fs, io, time::Duration};
+use serde::{Serialize, Deserialize};
+use url::Url;
+use crate::{config::{Config, SourceKind}, http::{self, HttpClient, Response}, util::atomic_write};
+
+mod google;
+mod bing;
+mod duckduckgo;
+mod searxng;
+pub(crate) use google::Google;
+pub(crate) use bing::Bing;
+pub(crate) use duckduckgo::DuckDuckGo;
+pub(crate) use searxng::Searxng;
+
+const DEFAULT_HEADERS: &[(&str, &str)] = &[
+    ("Accept", "text/html"),
+    ("User-Agent", "Mozilla/5.0 (X11; Linux x86_64; rv:78.0) Gecko/20100101 Firefox/78.0"),
+];
+
+/// A search engine listing pages which may link to giveaways.
+/// Its results are loaded page by page, until a page brings no new result or `max_pages` are loaded.
+pub(crate) trait DiscoverySource: Send + Sync {
+    /// The name displayed in the output
+    fn name(&self) -> &'static str;
+
+    /// The URL of a page of results, starting at 0.
+    fn page_url(&self, page: usize) -> String;
+
+    fn headers(&self) -> &'static [(&'static str, &'static str)] {
+        DEFAULT_HEADERS
+    }
+
+    /// The URLs of the results listed by a page, or an error if the body can't be understood.
+    fn parse(&self, body: &str) -> Result<Vec<String>, http::Error>;
+
+    /// Called at the start of each discovery run with the number of the run, before loading the pages.
+    fn new_cycle(&self, _run: usize) {}
+
+    /// Whether the response is a captcha or a ban page rather than results.
+    fn is_blocked(&self, _response: &Response) -> bool {
+        false
+    }
+
+    fn max_pages(&self) -> usize;
+
+    /// The delay between two pages of results
+    fn cooldown(&self) -> Duration;
+}
+
+/// Load a page of results of `source`.
+/// Being blocked is reported as a `429 Too Many Requests` status, so that the source is retried later.
+pub(crate) async fn search(source: &dyn DiscoverySource, client: &dyn HttpClient, page: usize) -> Result<Vec<String>, http::Error> {
+    let response = client.get(&source.page_url(page), source.headers()).await?.error_for_status()?;
+    if source.is_blocked(&response) {
+        return Err(http::Error::Status(429));
+    }
+    source.parse(&response.body)
+}
+
+/// What the discovery remembers between its runs.
+/// It is kept apart from the queue, which may be deleted or replaced.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct DiscoveryState {
+    #[serde(skip)]
+    path: String,
+    /// Number of discovery runs done, used to rotate the queries of the sources
+    #[serde(default)]
+    pub(crate) runs: usize,
+}
+
+impl DiscoveryState {
+    pub(crate) fn open(path: &str) -> DiscoveryState {
+        let mut state: DiscoveryState = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).expect("Failed to parse the discovery state file"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => DiscoveryState::default(),
+            Err(e) => panic!("Failed to read the discovery state file {}: {}", path, e),
+        };
+        state.path = path.to_string();
+        state
+    }
+
+    pub(crate) fn save(&self) {
+        let data = serde_json::to_string(self).expect("Can't serialize discovery state");
+        atomic_write(&self.path, data.as_bytes()).expect("Can't write data to discovery state file");
+    }
+}
+
+/// The form of `url` used to deduplicate results: absolute http(s) URLs only, without fragment.
+pub(crate) fn normalize(url: &str) -> Option<String> {
+    let mut url = Url::parse(url).ok()?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+    url.set_fragment(None);
+    Some(url.into())
+}
+
+/// The sources enabled in the configuration, in order.
+pub(crate) fn sources(config: &Config) -> Vec<Box<dyn DiscoverySource>> {
+    let max_pages = config.discovery.max_pages;
+    let cooldown = Duration::from_secs(config.cooldown as u64);
+    config.discovery.sources.iter().map(|kind| -> Box<dyn DiscoverySource> {
+        match kind {
+            SourceKind::Google => Box::new(Google::new(&config.discovery.google_queries, max_pages, cooldown)),
+            SourceKind::Bing => Box::new(Bing { max_pages, cooldown }),
+            SourceKind::DuckDuckGo => Box::new(DuckDuckGo { max_pages, cooldown }),
+            SourceKind::Searxng => {
+                let instance = match &config.discovery.searxng_url {
+                    Some(instance) => instance.trim_end_matches('/').to_string(),
+                    None => panic!("The searxng discovery source requires `searxng_url` in the configuration file"),
+                };
+                Box::new(Searxng { instance, max_pages, cooldown })
+            },
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::GoogleQuery, http::fake::FakeClient};
+
+    #[test]
+    fn normalization() {
+        assert_eq!(normalize("https://example.com/giveaway#comments"), Some(String::from("https://example.com/giveaway")));
+        assert_eq!(normalize("https://example.com"), Some(String::from("https://example.com/")));
+        assert_eq!(normalize("/search?q=gleam.io"), None);
+        assert_eq!(normalize("javascript:void(0)"), None);
+    }
+
+    #[test]
+    fn state() {
+        let path = std::env::temp_dir().join(format!("discovery-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut state = DiscoveryState::open(path);
+        assert_eq!(state.runs, 0);
+        state.runs += 1;
+        state.save();
+        assert_eq!(DiscoveryState::open(path).runs, 1);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn blocked() {
+        let source = Google::new(&[GoogleQuery::default()], 1, Duration::from_secs(0));
+        source.new_cycle(0);
+        let client = FakeClient::new()
+            .redirection(&source.page_url(0), "https://www.google.com/sorry/index?continue=https://www.google.com/search")
+            .page("https://www.google.com/sorry/index?continue=https://www.google.com/search", 200, "<html>Our systems have detected unusual traffic</html>");
+        assert!(matches!(search(&source, &client, 0).await, Err(http::Error::Status(429))));
+    }
+}
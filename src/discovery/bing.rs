@@ -0,0 +1,106 @@
+use std::time::Duration;
+use url::Url;
+use crate::{extract::decode_entities, http};
+use super::DiscoverySource;
+
+/// The HTML search of Bing, restricted to the last 24 hours.
+pub(crate) struct Bing {
+    pub(crate) max_pages: usize,
+    pub(crate) cooldown: Duration,
+}
+
+impl DiscoverySource for Bing {
+    fn name(&self) -> &'static str {
+        "Bing"
+    }
+
+    fn page_url(&self, page: usize) -> String {
+        format!("https://www.bing.com/search?q=%22gleam.io%22&filters=ex1%3a%22ez1%22&first={}", page * 10 + 1)
+    }
+
+    fn parse(&self, body: &str) -> Result<Vec<String>, http::Error> {
+        let mut results = Vec::new();
+        for block in body.split("<li class=\"b_algo\"").skip(1) {
+            let title = match block.find("<h2") {
+                Some(idx) => &block[idx..],
+                None => continue,
+            };
+            let href = match title.find("href=\"") {
+                Some(idx) => &title[idx + 6..],
+                None => continue,
+            };
+            let url = match href.find('"') {
+                Some(end) => decode_entities(&href[..end]),
+                None => continue,
+            };
+            let url = unwrap_click_url(&url).unwrap_or(url);
+            if !results.contains(&url) {
+                results.push(url);
+            }
+        }
+        Ok(results)
+    }
+
+    fn max_pages(&self) -> usize {
+        self.max_pages
+    }
+
+    fn cooldown(&self) -> Duration {
+        self.cooldown
+    }
+}
+
+/// The target of a Bing click-tracking URL (`https://www.bing.com/ck/a?...&u=a1<base64 url>`).
+fn unwrap_click_url(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    if !url.host_str()?.ends_with("bing.com") || url.path() != "/ck/a" {
+        return None;
+    }
+    let encoded = url.query_pairs().find(|(name, _value)| name == "u")?.1;
+    let decoded = base64_url_decode(encoded.strip_prefix("a1")?)?;
+    String::from_utf8(decoded).ok()
+}
+
+/// Decode unpadded base64 with the URL-safe alphabet.
+fn base64_url_decode(text: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in text.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' | b'+' => 62,
+            b'_' | b'/' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results() {
+        let bing = Bing { max_pages: 10, cooldown: Duration::from_secs(0) };
+        assert_eq!(bing.page_url(1), "https://www.bing.com/search?q=%22gleam.io%22&filters=ex1%3a%22ez1%22&first=11");
+
+        let page = std::fs::read_to_string("fixtures/bing/results.html").unwrap();
+        assert_eq!(bing.parse(&page).unwrap(), vec![
+            String::from("https://giveaways.example.com/2020/11/win-a-nintendo-switch/"),
+            String::from("https://www.reddit.com/r/giveaways/comments/jmq3nm/steam_keys/?sort=new&utm_source=bing"),
+            String::from("https://example.org/contest"),
+        ]);
+        assert!(bing.parse("<html><body><ol id=\"b_results\"></ol></body></html>").unwrap().is_empty());
+    }
+}
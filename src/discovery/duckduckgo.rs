@@ -0,0 +1,84 @@
+use std::time::Duration;
+use url::Url;
+use crate::{extract::{attributes, decode_entities}, http};
+use super::DiscoverySource;
+
+/// The HTML version of DuckDuckGo, restricted to the last day.
+pub(crate) struct DuckDuckGo {
+    pub(crate) max_pages: usize,
+    pub(crate) cooldown: Duration,
+}
+
+impl DiscoverySource for DuckDuckGo {
+    fn name(&self) -> &'static str {
+        "DuckDuckGo"
+    }
+
+    fn page_url(&self, page: usize) -> String {
+        // Each page lists 30 results
+        format!("https://html.duckduckgo.com/html/?q=%22gleam.io%22&df=d&s={}&dc={}", page * 30, page * 30 + 1)
+    }
+
+    fn parse(&self, body: &str) -> Result<Vec<String>, http::Error> {
+        let mut results = Vec::new();
+        for tag in body.split("<a ").skip(1) {
+            let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+            let attributes = attributes(tag);
+            let is_result = attributes.iter().any(|(name, value)| *name == "class" && value.split_whitespace().any(|class| class == "result__a"));
+            let href = attributes.iter().find(|(name, _value)| *name == "href").map(|(_name, value)| decode_entities(value));
+            let url = match (is_result, href) {
+                (true, Some(href)) => href,
+                _ => continue,
+            };
+            if let Some(url) = unwrap_redirection(&url) {
+                if !results.contains(&url) {
+                    results.push(url);
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    fn max_pages(&self) -> usize {
+        self.max_pages
+    }
+
+    fn cooldown(&self) -> Duration {
+        self.cooldown
+    }
+}
+
+/// The target of a result link, which usually goes through a redirection of DuckDuckGo.
+/// Returns `None` for the links of ads.
+fn unwrap_redirection(href: &str) -> Option<String> {
+    let absolute = match href.strip_prefix("//") {
+        Some(href) => format!("https://{}", href),
+        None => href.to_string(),
+    };
+    let url = Url::parse(&absolute).ok()?;
+    if !url.host_str()?.ends_with("duckduckgo.com") {
+        return Some(absolute);
+    }
+    match url.path() {
+        "/l/" => url.query_pairs().find(|(name, _value)| name == "uddg").map(|(_name, value)| value.into_owned()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results() {
+        let duckduckgo = DuckDuckGo { max_pages: 10, cooldown: Duration::from_secs(0) };
+        assert_eq!(duckduckgo.page_url(2), "https://html.duckduckgo.com/html/?q=%22gleam.io%22&df=d&s=60&dc=61");
+
+        let page = std::fs::read_to_string("fixtures/duckduckgo/results.html").unwrap();
+        assert_eq!(duckduckgo.parse(&page).unwrap(), vec![
+            String::from("https://giveaways.example.com/2020/11/steam-keys/"),
+            String::from("https://freestuff.example.org/posts/amd-gpu"),
+        ]);
+        assert!(duckduckgo.parse("<html><body><div class=\"no-results\">No results.</div></body></html>").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,228 @@
+use std::{sync::atomic::{AtomicUsize, Ordering}, time::Duration};
+use chrono::NaiveDate;
+use string_tools::{get_all_after, get_all_between_strict};
+use url::form_urlencoded;
+use crate::{config::{GoogleQuery, TimeWindow}, http::{self, Response}};
+use super::DiscoverySource;
+
+/// A query of the configuration, checked and ready to be sent.
+struct Search {
+    query: String,
+    domain: String,
+    /// The `tbs` parameter restricting the results to the time window
+    tbs: String,
+    hl: Option<String>,
+    gl: Option<String>,
+    max_pages: usize,
+}
+
+impl Search {
+    fn new(query: &GoogleQuery, max_pages: usize) -> Search {
+        let tbs = match &query.time_window {
+            TimeWindow::Recent(period) if ["h", "d", "w", "m", "y"].contains(&period.as_str()) => format!("qdr:{}", period),
+            TimeWindow::Recent(period) => panic!("Invalid time window {:?} for the Google query {:?}: expected h, d, w, m or y", period, query.query),
+            TimeWindow::Range { from, to } => {
+                let date = |date: &str| match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+                    Ok(date) => date.format("%-m/%-d/%Y").to_string(),
+                    Err(e) => panic!("Invalid date {:?} for the Google query {:?}: {}", date, query.query, e),
+                };
+                format!("cdr:1,cd_min:{},cd_max:{}", date(from), date(to))
+            },
+        };
+
+        Search {
+            query: query.query.clone(),
+            domain: query.domain.clone(),
+            tbs,
+            hl: query.hl.clone(),
+            gl: query.gl.clone(),
+            max_pages: query.max_pages.unwrap_or(max_pages),
+        }
+    }
+
+    fn url(&self, page: usize) -> String {
+        let mut parameters = form_urlencoded::Serializer::new(String::new());
+        parameters.append_pair("q", &self.query);
+        parameters.append_pair("tbs", &self.tbs);
+        if let Some(hl) = &self.hl {
+            parameters.append_pair("hl", hl);
+        }
+        if let Some(gl) = &self.gl {
+            parameters.append_pair("gl", gl);
+        }
+        parameters.append_pair("filter", "0");
+        parameters.append_pair("start", &(page * 10).to_string());
+        format!("https://{}/search?{}", self.domain, parameters.finish())
+    }
+}
+
+/// Google searches, using the next configured query on each discovery run.
+pub(crate) struct Google {
+    searches: Vec<Search>,
+    /// The index of the query used by the current run
+    current: AtomicUsize,
+    cooldown: Duration,
+}
+
+impl Google {
+    pub(crate) fn new(queries: &[GoogleQuery], max_pages: usize, cooldown: Duration) -> Google {
+        if queries.is_empty() {
+            panic!("The google discovery source requires at least one query in `google_queries`");
+        }
+        Google {
+            searches: queries.iter().map(|query| Search::new(query, max_pages)).collect(),
+            current: AtomicUsize::new(0),
+            cooldown,
+        }
+    }
+
+    fn search(&self) -> &Search {
+        &self.searches[self.current.load(Ordering::Relaxed)]
+    }
+}
+
+impl DiscoverySource for Google {
+    fn name(&self) -> &'static str {
+        "Google"
+    }
+
+    fn page_url(&self, page: usize) -> String {
+        self.search().url(page)
+    }
+
+    fn headers(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("Accept", "text/plain"),
+            ("User-Agent", "Mozilla/5.0 (X11; Linux x86_64; rv:71.0) Gecko/20100101 Firefox/71.0"),
+        ]
+    }
+
+    fn parse(&self, body: &str) -> Result<Vec<String>, http::Error> {
+        Ok(parse_results(body))
+    }
+
+    fn new_cycle(&self, run: usize) {
+        self.current.store(run % self.searches.len(), Ordering::Relaxed);
+    }
+
+    /// Google redirects the clients it suspects to a captcha.
+    fn is_blocked(&self, response: &Response) -> bool {
+        response.url.contains("/sorry/")
+    }
+
+    fn max_pages(&self) -> usize {
+        self.search().max_pages
+    }
+
+    fn cooldown(&self) -> Duration {
+        self.cooldown
+    }
+}
+
+fn parse_results(mut body: &str) -> Vec<String> {
+    let mut rep = Vec::new();
+    loop
+    {
+        let mut option1 = get_all_between_strict(body, "\"><a href=\"", "\"")
+            .map(|url| (Some(url), get_all_after(body, url)));
+        if let Some((Some(_url1), body1)) = option1 {
+            if !body1.starts_with("\" onmousedown=\"return rwt(") {
+                option1 = Some((None, body1));
+            }
+        }
+        let mut option2 = get_all_between_strict(body, "\" href=\"", "\"")
+            .map(|url| (Some(url), get_all_after(body, url)));
+        if let Some((_url2, body2)) = option2 {
+            if !body2.starts_with("\" data-ved=\"2a") {
+                option2 = Some((None, body2));
+            }
+        }
+
+        let (url, new_body) = match (option1, option2) {
+            (Some(option1), None) => option1,
+            (None, Some(option2)) => option2,
+            (None, None) => break,
+            (Some((Some(url1), body1)), Some((None, _body2))) => (Some(url1), body1),
+            (Some((None, _body1)), Some((Some(url2), body2))) => (Some(url2), body2),
+            (Some((url1, body1)), Some((_url2, body2))) if body1.len() > body2.len() => (url1, body1),
+            (Some((_url1, _body1)), Some((url2, body2))) => (url2, body2),
+        };
+
+        body = new_body;
+        if let Some(url) = url {
+            let url = url.to_string();
+            if !rep.contains(&url) {
+                rep.push(url);
+            }
+        }
+    }
+
+    rep
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{http::{self, fake::FakeClient}, discovery::search};
+
+    fn google(queries: &str) -> Google {
+        let config: crate::config::DiscoveryConfig = toml::from_str(queries).unwrap();
+        Google::new(&config.google_queries, config.max_pages, Duration::from_secs(0))
+    }
+
+    #[test]
+    fn get_full_url_test() {
+        let google = google("");
+        google.new_cycle(0);
+        assert_eq!(google.page_url(1), "https://www.google.com/search?q=%22gleam.io%22&tbs=qdr%3Ah&filter=0&start=10");
+        assert_eq!(google.max_pages(), 10);
+    }
+
+    #[test]
+    fn rotation() {
+        let google = google("
+            [[google_queries]]
+            query = '\"gleam.io\" sorteo'
+            time_window = 'd'
+            domain = 'www.google.es'
+            hl = 'es'
+            gl = 'es'
+            max_pages = 3
+
+            [[google_queries]]
+            time_window = { from = '2020-11-01', to = '2020-11-07' }
+        ");
+        google.new_cycle(0);
+        assert_eq!(google.page_url(0), "https://www.google.es/search?q=%22gleam.io%22+sorteo&tbs=qdr%3Ad&hl=es&gl=es&filter=0&start=0");
+        assert_eq!(google.max_pages(), 3);
+        google.new_cycle(1);
+        assert_eq!(google.page_url(0), "https://www.google.com/search?q=%22gleam.io%22&tbs=cdr%3A1%2Ccd_min%3A11%2F1%2F2020%2Ccd_max%3A11%2F7%2F2020&filter=0&start=0");
+        assert_eq!(google.max_pages(), 10);
+        google.new_cycle(2);
+        assert_eq!(google.max_pages(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid time window")]
+    fn invalid_time_window() {
+        google("[[google_queries]]\ntime_window = 'x'");
+    }
+
+    #[tokio::test]
+    async fn resolve_google_request() {
+        let google = google("");
+        google.new_cycle(0);
+        let results = std::fs::read_to_string("fixtures/google/results.html").unwrap();
+        let client = FakeClient::new()
+            .page(&google.page_url(0), 200, &results)
+            .page(&google.page_url(9), 200, "<html><body><div id=\"search\"></div></body></html>")
+            .page(&google.page_url(10), 429, "Too many requests");
+
+        assert_eq!(search(&google, &client, 0).await.unwrap(), vec![
+            String::from("https://www.youtube.com/watch?v=-DS1qgHjoJY"),
+            String::from("https://news.nestia.com/detail/Oculus-Quest-2---Infinite-Free-Games!/5222508"),
+        ]);
+        assert!(search(&google, &client, 9).await.unwrap().is_empty());
+        assert!(matches!(search(&google, &client, 10).await, Err(http::Error::Status(429))));
+    }
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+use serde::Deserialize;
+use crate::http;
+use super::DiscoverySource;
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    #[serde(default)]
+    results: Vec<SearchResult>,
+}
+
+#[derive(Deserialize)]
+struct SearchResult {
+    url: String,
+}
+
+/// The JSON API of a SearXNG instance, which must have the `json` format enabled.
+pub(crate) struct Searxng {
+    /// The base URL of the instance
+    pub(crate) instance: String,
+    pub(crate) max_pages: usize,
+    pub(crate) cooldown: Duration,
+}
+
+impl DiscoverySource for Searxng {
+    fn name(&self) -> &'static str {
+        "SearXNG"
+    }
+
+    fn page_url(&self, page: usize) -> String {
+        format!("{}/search?q=%22gleam.io%22&format=json&time_range=day&pageno={}", self.instance, page + 1)
+    }
+
+    fn headers(&self) -> &'static [(&'static str, &'static str)] {
+        &[
+            ("Accept", "application/json"),
+            ("User-Agent", "Mozilla/5.0 (X11; Linux x86_64; rv:78.0) Gecko/20100101 Firefox/78.0"),
+        ]
+    }
+
+    /// Instances answering with an error page rather than JSON fail, so that they are retried later.
+    fn parse(&self, body: &str) -> Result<Vec<String>, http::Error> {
+        let response: SearchResponse = serde_json::from_str(body).map_err(|e| http::Error::InvalidBody(e.to_string()))?;
+        let mut results = Vec::new();
+        for result in response.results {
+            if !results.contains(&result.url) {
+                results.push(result.url);
+            }
+        }
+        Ok(results)
+    }
+
+    fn max_pages(&self) -> usize {
+        self.max_pages
+    }
+
+    fn cooldown(&self) -> Duration {
+        self.cooldown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results() {
+        let searxng = Searxng { instance: String::from("https://searx.example.org"), max_pages: 10, cooldown: Duration::from_secs(0) };
+        assert_eq!(searxng.page_url(0), "https://searx.example.org/search?q=%22gleam.io%22&format=json&time_range=day&pageno=1");
+
+        let page = r#"{
+            "query": "\"gleam.io\"",
+            "number_of_results": 0,
+            "results": [
+                {"url": "https://giveaways.example.com/2020/11/steam-keys/", "title": "Steam keys", "engine": "bing", "score": 2.0},
+                {"url": "https://freestuff.example.org/posts/amd-gpu", "title": "AMD GPU giveaway", "engine": "duckduckgo", "score": 1.0},
+                {"url": "https://giveaways.example.com/2020/11/steam-keys/", "title": "Steam keys", "engine": "google", "score": 0.5}
+            ],
+            "answers": [],
+            "suggestions": []
+        }"#;
+        assert_eq!(searxng.parse(page).unwrap(), vec![
+            String::from("https://giveaways.example.com/2020/11/steam-keys/"),
+            String::from("https://freestuff.example.org/posts/amd-gpu"),
+        ]);
+        assert!(searxng.parse(r#"{"query": "\"gleam.io\"", "results": []}"#).unwrap().is_empty());
+        assert!(matches!(searxng.parse("<html><body>Too many requests</body></html>"), Err(http::Error::InvalidBody(_))));
+    }
+}
@@ -0,0 +1,394 @@
+/// A piece of a page in which links can be found, decoded according to where it comes from.
+enum Segment<'a> {
+    /// Text or attribute value, possibly containing HTML entities and JSON
+    Html(&'a str),
+    /// Content of a script, possibly containing escaped JSON strings
+    Script(&'a str),
+}
+
+/// An opening tag and its attributes.
+struct Tag<'a> {
+    name: &'a str,
+    attributes: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Tag<'a> {
+    fn attribute(&self, name: &str) -> Option<&'a str> {
+        self.attributes.iter().find(|(attribute, _value)| attribute.eq_ignore_ascii_case(name)).map(|(_attribute, value)| *value)
+    }
+}
+
+/// Find every gleam giveaway linked by a page and return their canonical URLs, without duplicates.
+/// Links are found in HTML attributes, text, inline scripts and JSON blobs, whatever their encoding.
+pub(crate) fn extract_gleam_links(body: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    for text in decoded_texts(body) {
+        for link in scan(&text) {
+            if !links.contains(&link) {
+                links.push(link);
+            }
+        }
+    }
+    links
+}
+
+/// Find every absolute http(s) URL of a page, without duplicates.
+pub(crate) fn extract_urls(body: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    for text in decoded_texts(body) {
+        let lowercase = text.to_ascii_lowercase();
+        let mut offset = 0;
+        while let Some(idx) = lowercase[offset..].find("http") {
+            let start = offset + idx;
+            offset = start + 4;
+            let rest = &lowercase[start..];
+            if !rest.starts_with("http://") && !rest.starts_with("https://") {
+                continue;
+            }
+            let end = text[start..].find(|c: char| c.is_whitespace() || c.is_control() || "\"'<>()[]{}\\^`|".contains(c)).unwrap_or_else(|| text.len() - start);
+            // Punctuation ending a sentence is not part of the URL
+            let url = text[start..start + end].trim_end_matches(|c: char| ".,;:!?".contains(c)).to_string();
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+    }
+    urls
+}
+
+/// The texts of a page in which links can be found, decoded in all the ways links are commonly encoded.
+fn decoded_texts(body: &str) -> Vec<String> {
+    let mut texts = Vec::new();
+    for segment in split_html(body).0 {
+        let decoded = match segment {
+            Segment::Html(text) => unescape_js(&decode_entities(text)),
+            Segment::Script(text) => decode_entities(&unescape_js(text)),
+        };
+        // Links are often percent-encoded in the parameters of redirections, sometimes twice
+        let once = percent_decode(&decoded);
+        let twice = percent_decode(&once);
+        texts.push(decoded);
+        texts.push(once);
+        texts.push(twice);
+    }
+    texts
+}
+
+/// Find the giveaways embedded in a page: gleam widgets, iframes and `data-` attributes pointing to gleam.
+/// Returns their canonical URLs, without duplicates.
+pub(crate) fn extract_embedded_gleam_links(body: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    for tag in split_html(body).1 {
+        let mut values = Vec::new();
+        let is_widget = tag.attribute("class").is_some_and(|class| class.split_whitespace().any(|class| class == "e-widget"));
+        if tag.name.eq_ignore_ascii_case("a") && is_widget {
+            values.extend(tag.attribute("href"));
+        }
+        if tag.name.eq_ignore_ascii_case("iframe") {
+            values.extend(tag.attribute("src"));
+        }
+        values.extend(tag.attributes.iter().filter(|(name, _value)| name.to_ascii_lowercase().starts_with("data-")).map(|(_name, value)| *value));
+
+        for value in values {
+            let decoded = unescape_js(&decode_entities(value));
+            for link in scan(&decoded).into_iter().chain(scan(&percent_decode(&decoded))) {
+                if !links.contains(&link) {
+                    links.push(link);
+                }
+            }
+        }
+    }
+    links
+}
+
+/// Split a page into its text, attribute values and scripts, and list its tags.
+fn split_html(body: &str) -> (Vec<Segment<'_>>, Vec<Tag<'_>>) {
+    let mut segments = Vec::new();
+    let mut tags = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find('<') {
+        segments.push(Segment::Html(&rest[..start]));
+        rest = &rest[start..];
+
+        // Comments may contain links too but have no attributes
+        if rest.starts_with("<!--") {
+            let end = rest.find("-->").map(|idx| idx + 3).unwrap_or(rest.len());
+            segments.push(Segment::Html(&rest[4..end]));
+            rest = &rest[end..];
+            continue;
+        }
+
+        let end = tag_end(rest);
+        let tag = rest[1..end].trim_end_matches('>');
+        let name = tag.split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or_default();
+        let attributes = attributes(&tag[name.len()..]);
+        segments.extend(attributes.iter().map(|(_name, value)| Segment::Html(value)));
+        let is_script = name.eq_ignore_ascii_case("script");
+        tags.push(Tag {
+            name,
+            attributes,
+        });
+        rest = &rest[end..];
+
+        if is_script {
+            let end = find_ignore_case(rest, "</script").unwrap_or(rest.len());
+            segments.push(Segment::Script(&rest[..end]));
+            rest = &rest[end..];
+        }
+    }
+    segments.push(Segment::Html(rest));
+    (segments, tags)
+}
+
+/// The index following the `>` closing the tag at the start of `html`, ignoring the ones in quoted values.
+fn tag_end(html: &str) -> usize {
+    let mut quote = None;
+    for (idx, c) in html.char_indices().skip(1) {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return idx + 1,
+            _ => (),
+        }
+    }
+    html.len()
+}
+
+/// The names and values of the attributes of a tag, without the quotes of the values.
+/// Attributes without values are ignored.
+pub(crate) fn attributes(tag: &str) -> Vec<(&str, &str)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag;
+    while let Some(idx) = rest.find('=') {
+        let name = rest[..idx].split_whitespace().last().unwrap_or_default();
+        rest = rest[idx + 1..].trim_start();
+        let (value, next) = match rest.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => {
+                let value = &rest[1..];
+                match value.find(quote) {
+                    Some(end) => (&value[..end], &value[end + 1..]),
+                    None => (value, ""),
+                }
+            },
+            _ => {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            },
+        };
+        attributes.push((name, value));
+        rest = next;
+    }
+    attributes
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack.to_ascii_lowercase().find(&needle.to_ascii_lowercase())
+}
+
+/// Decode the HTML entities that can appear in an URL.
+pub(crate) fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(idx) = rest.find('&') {
+        decoded.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+        let end = match rest[1..].find(|c: char| !c.is_ascii_alphanumeric() && c != '#') {
+            Some(end) if rest[1 + end..].starts_with(';') => end + 1,
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let entity = &rest[1..end];
+        let character = if let Some(number) = entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+            u32::from_str_radix(number, 16).ok().and_then(std::char::from_u32)
+        } else if let Some(number) = entity.strip_prefix('#') {
+            number.parse().ok().and_then(std::char::from_u32)
+        } else {
+            match entity {
+                "amp" => Some('&'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "sol" => Some('/'),
+                "colon" => Some(':'),
+                "period" => Some('.'),
+                "quest" => Some('?'),
+                "equals" => Some('='),
+                _ => None,
+            }
+        };
+        match character {
+            Some(character) => {
+                decoded.push(character);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Unescape the backslash escapes of JavaScript and JSON strings.
+fn unescape_js(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('u') => {
+                let code = chars.clone().take(4).collect::<String>();
+                match u32::from_str_radix(&code, 16).ok().and_then(std::char::from_u32) {
+                    Some(character) if code.len() == 4 => {
+                        unescaped.push(character);
+                        for _ in 0..4 {
+                            chars.next();
+                        }
+                    },
+                    _ => unescaped.push_str("\\u"),
+                }
+            },
+            Some('x') => {
+                let code = chars.clone().take(2).collect::<String>();
+                match u32::from_str_radix(&code, 16).ok().and_then(std::char::from_u32) {
+                    Some(character) if code.len() == 2 => {
+                        unescaped.push(character);
+                        chars.next();
+                        chars.next();
+                    },
+                    _ => unescaped.push_str("\\x"),
+                }
+            },
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Decode the percent-encoded characters, leaving invalid sequences untouched.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(byte) = std::str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|code| u8::from_str_radix(code, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Find the gleam links in decoded text and return their canonical URLs.
+fn scan(text: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    let lowercase = text.to_ascii_lowercase();
+    let mut offset = 0;
+    while let Some(idx) = lowercase[offset..].find("gleam.io/") {
+        let start = offset + idx;
+        offset = start + "gleam.io/".len();
+
+        // Reject other domains such as notgleam.io, but accept www.gleam.io
+        let before = lowercase[..start].strip_suffix("www.").unwrap_or(&lowercase[..start]);
+        if let Some(c) = before.chars().last() {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '@' {
+                continue;
+            }
+        }
+
+        let path = &text[offset..];
+        let end = path.find(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '_' && c != '/').unwrap_or(path.len());
+        if let Some(id) = giveaway_id(&path[..end]) {
+            links.push(format!("https://gleam.io/{}/-", id));
+        }
+    }
+    links
+}
+
+/// The id of the giveaway at a path of gleam.io: `XXXXX/name` or `competitions/XXXXX-name`.
+fn giveaway_id(path: &str) -> Option<&str> {
+    let (path, separator) = match path.strip_prefix("competitions/") {
+        Some(path) => (path, b'-'),
+        None => (path, b'/'),
+    };
+    if path.len() > 5 && path.as_bytes()[5] == separator && path[..5].chars().all(|c| c.is_ascii_alphanumeric()) {
+        Some(&path[..5])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| format!("https://gleam.io/{}/-", id)).collect()
+    }
+
+    #[test]
+    fn anchors() {
+        let page = include_str!("../fixtures/extract/anchors.html");
+        assert_eq!(extract_gleam_links(page), links(&["aBcDe", "FgHiJ", "kLmNo", "pQrSt", "uVwXy"]));
+    }
+
+    #[test]
+    fn scripts() {
+        let page = include_str!("../fixtures/extract/scripts.html");
+        assert_eq!(extract_gleam_links(page), links(&["A1b2C", "D3e4F", "G5h6I"]));
+    }
+
+    #[test]
+    fn redirections() {
+        let page = include_str!("../fixtures/extract/redirections.html");
+        assert_eq!(extract_gleam_links(page), links(&["Qw3rT", "Yu1oP"]));
+    }
+
+    #[test]
+    fn urls() {
+        let page = include_str!("../fixtures/extract/redirections.html");
+        let urls = extract_urls(page);
+        assert!(urls.contains(&String::from("https://l.facebook.com/l.php?u=https%3A%2F%2Fgleam.io%2FQw3rT%2Fgiveaway&h=AT0xyz")));
+        assert!(urls.contains(&String::from("https://gleam.io/Qw3rT/giveaway&h=AT0xyz")));
+        assert!(urls.contains(&String::from("https://bit.ly/3xYzAbC")));
+        assert!(urls.contains(&String::from("https://t.co/AbCdEfGh")));
+    }
+
+    #[test]
+    fn embedded() {
+        let page = include_str!("../fixtures/extract/embedded.html");
+        assert_eq!(extract_gleam_links(page), links(&["WiDgT", "FrAmE", "DaTaA", "NoTeM"]));
+        assert_eq!(extract_embedded_gleam_links(page), links(&["WiDgT", "FrAmE", "DaTaA"]));
+        assert!(extract_embedded_gleam_links(include_str!("../fixtures/extract/anchors.html")).is_empty());
+    }
+
+    #[test]
+    fn no_giveaway() {
+        let page = include_str!("../fixtures/extract/no_giveaway.html");
+        assert!(extract_gleam_links(page).is_empty());
+    }
+
+    #[test]
+    fn decoding() {
+        assert_eq!(decode_entities("a&amp;b&#47;c&#x2F;d&sol;e&unknown;f&g"), "a&b/c/d/e&unknown;f&g");
+        assert_eq!(unescape_js(r#"https:\/\/gleam.io/abc\x2Fd\\"#), "https://gleam.io/abc/d\\");
+        assert_eq!(percent_decode("https%3A%2F%2Fgleam.io%2f 100% %zz"), "https://gleam.io/ 100% %zz");
+    }
+}
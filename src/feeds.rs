@@ -0,0 +1,210 @@
+use std::{collections::{HashMap, HashSet}, fs, io};
+use serde::{Serialize, Deserialize};
+use crate::{extract::{extract_gleam_links, attributes, decode_entities}, http::{self, HttpClient}, util::atomic_write, xml::{opening_tags, element_text, blocks}};
+
+/// An item of an RSS feed or an entry of an Atom feed.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Item {
+    /// The guid of the item, or its link if it has none
+    pub(crate) id: String,
+    /// The page of the item
+    pub(crate) link: Option<String>,
+    /// The gleam links found in the content and the links of the item
+    pub(crate) gleam_links: Vec<String>,
+}
+
+/// The page of an Atom entry: the `href` of its alternate link.
+fn atom_link(entry: &str) -> Option<String> {
+    opening_tags(entry, "link").find_map(|(_end, tag)| {
+        let attributes = attributes(tag);
+        let rel = attributes.iter().find(|(name, _value)| *name == "rel").map(|(_name, value)| *value);
+        let href = attributes.iter().find(|(name, _value)| *name == "href").map(|(_name, value)| *value)?;
+        match rel {
+            None | Some("alternate") => Some(decode_entities(href)),
+            _ => None,
+        }
+    })
+}
+
+/// Parse an RSS or Atom feed, keeping the order of its items.
+pub(crate) fn parse(content: &str) -> Vec<Item> {
+    let (items, is_atom) = match blocks(content, "item") {
+        items if !items.is_empty() => (items, false),
+        _ => (blocks(content, "entry"), true),
+    };
+
+    items.into_iter().filter_map(|item| {
+        let link = if is_atom { atom_link(item) } else { element_text(item, "link") };
+        let id = element_text(item, if is_atom { "id" } else { "guid" }).or_else(|| link.clone())?;
+        Some(Item {
+            id,
+            link,
+            gleam_links: extract_gleam_links(item),
+        })
+    }).collect()
+}
+
+pub(crate) async fn fetch(client: &dyn HttpClient, url: &str) -> Result<Vec<Item>, http::Error> {
+    let headers = [
+        ("Accept", "application/rss+xml,application/atom+xml,application/xml,text/xml"),
+        ("User-Agent", "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:78.0) Gecko/20100101 Firefox/78.0"),
+    ];
+    let response = client.get(url, &headers).await?.error_for_status()?;
+    Ok(parse(&response.body))
+}
+
+/// The number of item ids remembered for each feed, in addition to the ones still in the feed.
+const MAX_SEEN_ITEMS: usize = 200;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FeedState {
+    /// The ids of the items seen, the newest first
+    #[serde(default)]
+    seen_items: Vec<String>,
+    /// The id of the newest item seen, as saved by the previous versions
+    #[serde(default, skip_serializing)]
+    last_item: Option<String>,
+    polled_at: u64,
+}
+
+/// The items seen in each feed, so that only the new items are used.
+pub(crate) struct FeedStore {
+    path: String,
+    feeds: HashMap<String, FeedState>,
+}
+
+impl FeedStore {
+    pub(crate) fn open(path: &str) -> FeedStore {
+        let mut feeds: HashMap<String, FeedState> = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).expect("Failed to parse the feeds file"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => panic!("Failed to read the feeds file {}: {}", path, e),
+        };
+        for state in feeds.values_mut() {
+            if let Some(last_item) = state.last_item.take() {
+                state.seen_items.insert(0, last_item);
+            }
+        }
+
+        FeedStore {
+            path: path.to_string(),
+            feeds,
+        }
+    }
+
+    /// Keep the items of `feed` which were not seen yet, in the order of the feed, and remember them.
+    /// The ids of all the items of the feed are remembered, so that old items moving in the feed are not new again.
+    pub(crate) fn new_items(&mut self, feed: &str, items: Vec<Item>, now: u64) -> Vec<Item> {
+        let state = self.feeds.entry(feed.to_string()).or_insert_with(|| FeedState {
+            seen_items: Vec::new(),
+            last_item: None,
+            polled_at: now,
+        });
+        let previous = state.seen_items.iter().collect::<HashSet<_>>();
+        let mut seen_items = items.iter().map(|item| item.id.clone()).collect::<Vec<_>>();
+        let max_seen_items = seen_items.len().max(MAX_SEEN_ITEMS);
+        let new_items = items.into_iter().filter(|item| !previous.contains(&item.id)).collect::<Vec<_>>();
+
+        for id in &state.seen_items {
+            if seen_items.len() >= max_seen_items {
+                break;
+            }
+            if !seen_items.contains(id) {
+                seen_items.push(id.clone());
+            }
+        }
+        state.seen_items = seen_items;
+        state.polled_at = now;
+        new_items
+    }
+
+    pub(crate) fn save(&self) {
+        let data = serde_json::to_string(&self.feeds).expect("Can't serialize feeds");
+        atomic_write(&self.path, data.as_bytes()).expect("Can't write data to feeds file");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: &str, link: &str, gleam_ids: &[&str]) -> Item {
+        Item {
+            id: id.to_string(),
+            link: Some(link.to_string()),
+            gleam_links: gleam_ids.iter().map(|id| format!("https://gleam.io/{}/-", id)).collect(),
+        }
+    }
+
+    #[test]
+    fn rss() {
+        let feed = fs::read_to_string("fixtures/feeds/rss.xml").unwrap();
+        assert_eq!(parse(&feed), vec![
+            item("https://giveaways.example.com/?p=1042", "https://giveaways.example.com/2020/11/win-a-nintendo-switch/", &["3uSs9", "OWMw8"]),
+            item("https://giveaways.example.com/?p=1041", "https://giveaways.example.com/2020/11/steam-keys/", &["7qHd6"]),
+            item("https://giveaways.example.com/?p=1040", "https://giveaways.example.com/2020/11/weekly-recap/", &[]),
+        ]);
+    }
+
+    #[test]
+    fn atom() {
+        let feed = fs::read_to_string("fixtures/feeds/atom.xml").unwrap();
+        assert_eq!(parse(&feed), vec![
+            item("tag:freestuff.example.org,2020:posts/amd-gpu", "https://freestuff.example.org/posts/amd-gpu", &["2zAsX"]),
+            item("tag:freestuff.example.org,2020:posts/nothing", "https://freestuff.example.org/posts/nothing", &[]),
+        ]);
+    }
+
+    #[test]
+    fn last_seen_item() {
+        let feed = fs::read_to_string("fixtures/feeds/rss.xml").unwrap();
+        let mut store = FeedStore::open("/nonexistent/feeds.json");
+        assert_eq!(store.new_items("https://giveaways.example.com/feed", parse(&feed), 0).len(), 3);
+        assert!(store.new_items("https://giveaways.example.com/feed", parse(&feed), 0).is_empty());
+
+        // Two items were published since the last poll
+        let mut items = parse(&feed);
+        items.insert(0, item("https://giveaways.example.com/?p=1044", "https://giveaways.example.com/2020/11/b/", &[]));
+        items.insert(0, item("https://giveaways.example.com/?p=1045", "https://giveaways.example.com/2020/11/c/", &[]));
+        let new_items = store.new_items("https://giveaways.example.com/feed", items, 0);
+        assert_eq!(new_items.iter().map(|item| item.id.as_str()).collect::<Vec<_>>(), vec![
+            "https://giveaways.example.com/?p=1045",
+            "https://giveaways.example.com/?p=1044",
+        ]);
+        assert_eq!(store.new_items("https://giveaways.example.com/other-feed", parse(&feed), 0).len(), 3);
+
+        // The newest item was deleted and an old one was updated and moved up
+        let mut items = parse(&feed);
+        items.insert(0, item("https://giveaways.example.com/?p=1046", "https://giveaways.example.com/2020/11/d/", &[]));
+        items.swap(1, 3);
+        let new_items = store.new_items("https://giveaways.example.com/feed", items, 0);
+        assert_eq!(new_items.iter().map(|item| item.id.as_str()).collect::<Vec<_>>(), vec!["https://giveaways.example.com/?p=1046"]);
+    }
+
+    #[test]
+    fn seen_items_limit() {
+        let mut store = FeedStore::open("/nonexistent/feeds.json");
+        let items = |range: std::ops::Range<usize>| range.rev().map(|i| item(&i.to_string(), "https://example.com/", &[])).collect::<Vec<_>>();
+        assert_eq!(store.new_items("feed", items(0..10), 0).len(), 10);
+        assert_eq!(store.new_items("feed", items(10..MAX_SEEN_ITEMS + 10), 0).len(), MAX_SEEN_ITEMS);
+        assert_eq!(store.feeds["feed"].seen_items.len(), MAX_SEEN_ITEMS);
+        assert_eq!(store.new_items("feed", items(9..10), 0).len(), 1);
+    }
+
+    #[test]
+    fn previous_format() {
+        let path = std::env::temp_dir().join(format!("feeds-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, r#"{"https://giveaways.example.com/feed":{"last_item":"https://giveaways.example.com/?p=1041","polled_at":0}}"#).unwrap();
+        let mut store = FeedStore::open(path);
+        fs::remove_file(path).unwrap();
+
+        // Only the last item was remembered, so the older ones are used once more
+        let feed = fs::read_to_string("fixtures/feeds/rss.xml").unwrap();
+        let new_items = store.new_items("https://giveaways.example.com/feed", parse(&feed), 0);
+        assert_eq!(new_items.iter().map(|item| item.id.as_str()).collect::<Vec<_>>(), vec![
+            "https://giveaways.example.com/?p=1042",
+            "https://giveaways.example.com/?p=1040",
+        ]);
+    }
+}
@@ -1,6 +1,9 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 use format::parsing::*;
 use format::prelude::*;
+use crate::{http::{self, HttpClient}, retry::RequestError};
+
+pub const GLEAM_HOST: &str = "gleam.io";
 
 /// Extract the id of the giveaway from an url.
 pub fn get_gleam_id(url: &str) -> Option<&str> {
@@ -13,45 +16,60 @@ pub fn get_gleam_id(url: &str) -> Option<&str> {
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     InvalidGleamUrl,
-    NetworkError(minreq::Error),
+    NetworkError(http::Error),
     ParseError(ParseError),
 }
 
-pub fn fetch(url: &str) -> Result<SearchResult, Error> {
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidGleamUrl => write!(f, "invalid gleam url"),
+            Error::NetworkError(e) => e.fmt(f),
+            Error::ParseError(e) => write!(f, "invalid giveaway page: {:?}", e),
+        }
+    }
+}
+
+impl RequestError for Error {
+    fn is_transient(&self) -> bool {
+        matches!(self, Error::NetworkError(e) if e.is_transient())
+    }
+
+    fn is_connection_error(&self) -> bool {
+        matches!(self, Error::NetworkError(e) if e.is_connection_error())
+    }
+}
+
+pub async fn fetch(client: &dyn HttpClient, url: &str) -> Result<SearchResult, Error> {
     let giveaway_id = match get_gleam_id(url) {
         Some(id) => id,
         None => return Err(Error::InvalidGleamUrl),
     };
 
     let url = format!("https://gleam.io/{}/-", giveaway_id);
-    let response = match minreq::get(&url)
-        .with_header("Host", "gleam.io")
-        .with_header(
-            "User-Agent",
-            "Mozilla/5.0 (X11; Linux x86_64; rv:72.0) Gecko/20100101 Firefox/72.0",
-        )
-        .with_header("Accept", "text/html")
-        .with_header("DNT", "1")
-        .with_header("Connection", "keep-alive")
-        .with_header("Upgrade-Insecure-Requests", "1")
-        .with_header("TE", "Trailers")
-        .send()
-    {
+    let headers = [
+        ("User-Agent", "Mozilla/5.0 (X11; Linux x86_64; rv:72.0) Gecko/20100101 Firefox/72.0"),
+        ("Accept", "text/html"),
+        ("DNT", "1"),
+        ("Upgrade-Insecure-Requests", "1"),
+    ];
+    let response = match client.get(&url, &headers).await {
         Ok(response) => response,
         Err(e) => {
             return Err(Error::NetworkError(e));
         },
     };
+    // The pages of deleted giveaways are parsed, but the other errors don't tell anything about the giveaway
+    match response.status {
+        200..=299 | 404 | 410 => (),
+        status => return Err(Error::NetworkError(http::Error::Status(status))),
+    }
 
-    let body = match response.as_str() {
-        Ok(body) => body,
-        Err(e) => return Err(Error::NetworkError(e)),
-    };
-
-    let (giveaway, entry_count) = match format::parsing::parse_html(body) {
-        Ok((giveaway, _, entry_count)) => (giveaway, entry_count),
+    let (giveaway, entry_count) = match format::parsing::parse_html(&response.body) {
+        Ok((giveaway, entry_count)) => (giveaway, entry_count),
         Err(e) => return Err(Error::ParseError(e)),
     };
     
@@ -66,36 +84,61 @@ pub fn fetch(url: &str) -> Result<SearchResult, Error> {
     };
     
     Ok(SearchResult {
-        giveaway: giveaway.into(),
+        giveaway,
         last_updated: now,
-        referers: vec![url],
+        referers: Vec::new(),
         entry_count,
         entry_evolution,
     })
 }
 #[cfg(test)]
 mod tests {
-    use std::{thread::sleep, time::Duration};
     use super::*;
+    use crate::http::fake::FakeClient;
 
-    #[test]
-    fn test_giveaway_struct() {
-        let giveaway =
-            fetch("https://gleam.io/29CPn/-2-alok-gveaway-and-12000-diamonds-")
-                .unwrap();
-        println!("{:?}", giveaway);
-            sleep(Duration::from_secs(15));
-        let giveaway =
-            fetch("https://gleam.io/SB3C7/-")
-               .unwrap();
-        println!("{:?}", giveaway);
-        sleep(Duration::from_secs(15));
-        let giveaway = fetch("https://gleam.io/8nTqy/amd-5700xt-gpu").unwrap();
-        println!("{:?}", giveaway);
-        sleep(Duration::from_secs(15));
-        let giveaway =
-            fetch("https://gleam.io/ff3QT/win-an-ipad-pro-with-canstar").unwrap();
-        println!("{:?}", giveaway);
+    #[tokio::test]
+    async fn test_giveaway_struct() {
+        let no_giveaway = std::fs::read_to_string("fixtures/extract/no_giveaway.html").unwrap();
+        let client = FakeClient::new()
+            .page("https://gleam.io/8nTqy/-", 200, &no_giveaway)
+            .page("https://gleam.io/SB3C7/-", 503, "Service unavailable")
+            .page("https://gleam.io/2zAsX/-", 403, "Access denied");
+
+        assert!(matches!(fetch(&client, "https://example.com/8nTqy/amd-5700xt-gpu").await, Err(Error::InvalidGleamUrl)));
+        assert!(client.requests().is_empty());
+
+        // The canonical URL of the giveaway is requested
+        assert!(matches!(fetch(&client, "https://gleam.io/8nTqy/amd-5700xt-gpu").await, Err(Error::ParseError(_))));
+        assert_eq!(client.requests()[0].0, "https://gleam.io/8nTqy/-");
+
+        match fetch(&client, "https://gleam.io/SB3C7/-").await {
+            Err(Error::NetworkError(e)) => assert!(e.is_transient()),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(matches!(fetch(&client, "https://gleam.io/2zAsX/-").await, Err(Error::NetworkError(http::Error::Status(403)))));
+        assert!(matches!(fetch(&client, "https://gleam.io/ff3QT/win-an-ipad-pro-with-canstar").await, Err(Error::NetworkError(http::Error::Network(_)))));
+    }
+
+    #[tokio::test]
+    async fn fetching() {
+        let page = std::fs::read_to_string("fixtures/gleam/giveaway.html").unwrap();
+        let client = FakeClient::new().page("https://gleam.io/K5Ihy/-", 200, &page);
+
+        let giveaway = fetch(&client, "https://gleam.io/K5Ihy/byarteers-2800-vbucks-giveaway").await.unwrap();
+        assert_eq!(giveaway.get_url(), "https://gleam.io/K5Ihy/-");
+        assert_eq!(giveaway.get_name(), "byArteer's 2800 V-Bucks giveaway!");
+        assert_eq!(giveaway.ends_at(), 1605394799);
+        assert_eq!(giveaway.giveaway.campaign.site_name, "byArteer");
+        assert_eq!(giveaway.giveaway.incentive.incentive_type, "Prize");
+        assert_eq!(giveaway.giveaway.incentive.incentive_description, "<p>If you want to help me out with future giveaways, be sure to use code \"byArteer\" in the item shop! #ad</p>");
+        assert_eq!(giveaway.giveaway.entry_methods.iter().map(|method| method.entry_type.as_str()).collect::<Vec<_>>(), vec!["instagram_visit_profile", "twitter_follow"]);
+        assert!(giveaway.referers.is_empty());
+
+        // The entry count is recorded at the time of the refresh
+        assert_eq!(giveaway.entry_count, Some(721));
+        let entry_evolution = giveaway.entry_evolution.unwrap();
+        assert_eq!(entry_evolution.len(), 1);
+        assert_eq!(entry_evolution.get(&giveaway.last_updated), Some(&721));
     }
 
     #[test]
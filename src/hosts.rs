@@ -0,0 +1,234 @@
+use std::{collections::HashMap, fs, io, sync::Mutex};
+use serde::{Serialize, Deserialize};
+use crate::{config::{Config, HostsConfig}, util::atomic_write};
+
+/// A new giveaway is worth this many gleam links when computing the yield of a host.
+const NEW_GIVEAWAY_WEIGHT: u64 = 5;
+
+fn yield_of(pages: u64, gleam_links: u64, new_giveaways: u64) -> f64 {
+    (gleam_links + new_giveaways * NEW_GIVEAWAY_WEIGHT) as f64 / pages.max(1) as f64
+}
+
+/// What the result pages of a host brought us.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct HostStats {
+    pub(crate) pages: u64,
+    pub(crate) gleam_links: u64,
+    pub(crate) new_giveaways: u64,
+    /// The counts since the yield of the host was last evaluated
+    #[serde(default)]
+    recent_pages: u64,
+    #[serde(default)]
+    recent_links: u64,
+    #[serde(default)]
+    recent_giveaways: u64,
+    /// Number of consecutive evaluations which blocked the host
+    #[serde(default)]
+    pub(crate) strikes: u32,
+    #[serde(default)]
+    pub(crate) blocked_until: u64,
+}
+
+impl HostStats {
+    pub(crate) fn score(&self) -> f64 {
+        yield_of(self.pages, self.gleam_links, self.new_giveaways)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum HostStatus {
+    /// Listed in the allow list of the configuration
+    Allowed,
+    /// Listed in the block list of the configuration
+    Blocked,
+    /// Blocked because of its low yield until this timestamp
+    BlockedUntil(u64),
+    Active,
+}
+
+/// Whether `host` or one of its parent domains is in `list`.
+fn is_listed(list: &[String], host: &str) -> bool {
+    let mut domain = host;
+    loop {
+        if list.iter().any(|listed| listed == domain) {
+            return true;
+        }
+        match domain.find('.') {
+            Some(idx) => domain = &domain[idx + 1..],
+            None => return false,
+        }
+    }
+}
+
+/// The yield statistics of the hosts of the result pages, kept across runs.
+/// Hosts with a low yield are blocked for a period growing each time they are blocked again.
+pub(crate) struct HostStore {
+    config: HostsConfig,
+    hosts: Mutex<HashMap<String, HostStats>>,
+}
+
+impl HostStore {
+    pub(crate) fn open(config: &HostsConfig) -> HostStore {
+        let hosts = match fs::read_to_string(&config.file) {
+            Ok(content) => serde_json::from_str(&content).expect("Failed to parse the hosts file"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => panic!("Failed to read the hosts file {}: {}", config.file, e),
+        };
+
+        HostStore {
+            config: config.clone(),
+            hosts: Mutex::new(hosts),
+        }
+    }
+
+    pub(crate) fn status(&self, host: &str, now: u64) -> HostStatus {
+        if is_listed(&self.config.allow, host) {
+            return HostStatus::Allowed;
+        }
+        if is_listed(&self.config.block, host) {
+            return HostStatus::Blocked;
+        }
+        match self.hosts.lock().unwrap().get(host) {
+            Some(stats) if stats.blocked_until > now => HostStatus::BlockedUntil(stats.blocked_until),
+            _ => HostStatus::Active,
+        }
+    }
+
+    /// Whether the pages of `host` may be loaded.
+    pub(crate) fn allows(&self, host: &str, now: u64) -> bool {
+        matches!(self.status(host, now), HostStatus::Allowed | HostStatus::Active)
+    }
+
+    /// Record a page loaded from `host`, containing `gleam_links` links.
+    pub(crate) fn record_page(&self, host: &str, gleam_links: usize) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let stats = hosts.entry(host.to_string()).or_default();
+        stats.pages += 1;
+        stats.gleam_links += gleam_links as u64;
+        stats.recent_pages += 1;
+        stats.recent_links += gleam_links as u64;
+    }
+
+    /// Record a giveaway which was not in the database yet, found on a page of `host`.
+    pub(crate) fn record_giveaway(&self, host: &str) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let stats = hosts.entry(host.to_string()).or_default();
+        stats.new_giveaways += 1;
+        stats.recent_giveaways += 1;
+    }
+
+    /// Evaluate the hosts from which enough pages were loaded since their last evaluation, and block the ones with a low yield.
+    /// Returns the newly blocked hosts with the end of their block.
+    pub(crate) fn update_blocklist(&self, now: u64) -> Vec<(String, u64)> {
+        let mut blocked = Vec::new();
+        let mut hosts = self.hosts.lock().unwrap();
+        for (host, stats) in hosts.iter_mut() {
+            if stats.recent_pages < self.config.min_pages {
+                continue;
+            }
+            let recent_yield = yield_of(stats.recent_pages, stats.recent_links, stats.recent_giveaways);
+            stats.recent_pages = 0;
+            stats.recent_links = 0;
+            stats.recent_giveaways = 0;
+            if is_listed(&self.config.allow, host) {
+                continue;
+            }
+
+            if recent_yield < self.config.min_yield {
+                stats.strikes += 1;
+                let duration = self.config.block_duration.saturating_mul(1 << (stats.strikes - 1).min(16)).min(self.config.max_block_duration);
+                stats.blocked_until = now + duration;
+                blocked.push((host.clone(), stats.blocked_until));
+            } else {
+                stats.strikes = 0;
+            }
+        }
+        blocked
+    }
+
+    /// The statistics of all the hosts, the highest yields first.
+    pub(crate) fn scores(&self) -> Vec<(String, HostStats)> {
+        let hosts = self.hosts.lock().unwrap();
+        let mut scores = hosts.iter().map(|(host, stats)| (host.clone(), stats.clone())).collect::<Vec<_>>();
+        scores.sort_by(|(host1, stats1), (host2, stats2)| stats2.score().partial_cmp(&stats1.score()).unwrap().then(host1.cmp(host2)));
+        scores
+    }
+
+    pub(crate) fn save(&self) {
+        let hosts = self.hosts.lock().unwrap();
+        let data = serde_json::to_string(&*hosts).expect("Can't serialize hosts");
+        atomic_write(&self.config.file, data.as_bytes()).expect("Can't write data to hosts file");
+    }
+}
+
+/// Display the yield of the hosts and whether they are blocked.
+pub(crate) fn hosts_command(config: &Config) {
+    let store = HostStore::open(&config.hosts);
+    let now = std::time::SystemTime::now().duration_since(std::time::SystemTime::UNIX_EPOCH).unwrap().as_secs();
+
+    println!("{:>8}\t{:>8}\t{:>8}\t{:>8}\t{:<16}\thost", "pages", "links", "new", "score", "status");
+    for (host, stats) in store.scores() {
+        let status = match store.status(&host, now) {
+            HostStatus::Allowed => String::from("allowed"),
+            HostStatus::Blocked => String::from("blocked"),
+            HostStatus::BlockedUntil(until) => format!("blocked for {}h", (until - now).div_ceil(3600)),
+            HostStatus::Active => String::from("active"),
+        };
+        println!("{:>8}\t{:>8}\t{:>8}\t{:>8.3}\t{:<16}\t{}", stats.pages, stats.gleam_links, stats.new_giveaways, stats.score(), status, host);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> HostStore {
+        let config: HostsConfig = toml::from_str(
+            "file = \"/nonexistent/hosts.json\"\nmin_pages = 4\nmin_yield = 0.5\nblock_duration = 100\nmax_block_duration = 300\nallow = [\"example.org\"]\nblock = [\"spam.example.com\"]"
+        ).unwrap();
+        HostStore::open(&config)
+    }
+
+    fn load_pages(store: &HostStore, host: &str, pages: usize, gleam_links: usize) {
+        for page in 0..pages {
+            store.record_page(host, if page < gleam_links { 1 } else { 0 });
+        }
+    }
+
+    #[test]
+    fn blocklist() {
+        let store = store();
+        load_pages(&store, "example.com", 3, 0);
+        assert!(store.update_blocklist(0).is_empty());
+
+        // The block lasts longer each time the host is blocked again
+        load_pages(&store, "example.com", 1, 0);
+        assert_eq!(store.update_blocklist(0), vec![(String::from("example.com"), 100)]);
+        assert!(!store.allows("example.com", 50));
+        assert!(store.allows("example.com", 100));
+        load_pages(&store, "example.com", 4, 1);
+        assert_eq!(store.update_blocklist(100), vec![(String::from("example.com"), 300)]);
+        load_pages(&store, "example.com", 4, 1);
+        assert_eq!(store.update_blocklist(300), vec![(String::from("example.com"), 600)]);
+
+        // A good yield resets the back-off
+        load_pages(&store, "example.com", 4, 1);
+        store.record_giveaway("example.com");
+        assert!(store.update_blocklist(600).is_empty());
+        load_pages(&store, "example.com", 4, 0);
+        assert_eq!(store.update_blocklist(600), vec![(String::from("example.com"), 700)]);
+    }
+
+    #[test]
+    fn manual_lists() {
+        let store = store();
+        load_pages(&store, "www.example.org", 4, 0);
+        assert!(store.update_blocklist(0).is_empty());
+        assert_eq!(store.status("www.example.org", 0), HostStatus::Allowed);
+
+        assert_eq!(store.status("spam.example.com", 0), HostStatus::Blocked);
+        assert_eq!(store.status("cdn.spam.example.com", 0), HostStatus::Blocked);
+        assert_eq!(store.status("example.com", 0), HostStatus::Active);
+        assert!(!store.allows("spam.example.com", 0));
+    }
+}
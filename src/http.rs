@@ -0,0 +1,199 @@
+use std::{fmt, time::Duration};
+use futures::future::BoxFuture;
+use reqwest::{Proxy, redirect::Policy};
+use crate::retry::RequestError;
+
+/// The answer of a server to a request.
+#[derive(Debug, Clone)]
+pub(crate) struct Response {
+    pub(crate) status: u16,
+    /// The URL of the response, after the redirections
+    pub(crate) url: String,
+    headers: Vec<(String, String)>,
+    pub(crate) body: String,
+}
+
+impl Response {
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(header, _value)| header.eq_ignore_ascii_case(name)).map(|(_header, value)| value.as_str())
+    }
+
+    /// Turn the client and server error statuses into errors.
+    pub(crate) fn error_for_status(self) -> Result<Response, Error> {
+        if self.status >= 400 {
+            return Err(Error::Status(self.status));
+        }
+        Ok(self)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Error {
+    /// The request could not be sent or the response could not be read
+    Network(String),
+    /// The server answered with an error status
+    Status(u16),
+    /// The body of the response is not in the expected format
+    InvalidBody(String),
+}
+
+impl RequestError for Error {
+    fn is_transient(&self) -> bool {
+        match self {
+            Error::Network(_) => true,
+            Error::Status(status) => *status >= 500 || *status == 429,
+            // Usually an error page, which may not be served again
+            Error::InvalidBody(_) => true,
+        }
+    }
+
+    fn is_connection_error(&self) -> bool {
+        matches!(self, Error::Network(_))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Network(e) => write!(f, "{}", e),
+            Error::Status(status) => write!(f, "the server answered with the status {}", status),
+            Error::InvalidBody(e) => write!(f, "invalid response: {}", e),
+        }
+    }
+}
+
+/// Sends the requests of the crawler, so that the network can be replaced by a fake in tests.
+pub(crate) trait HttpClient: Send + Sync {
+    /// Send a GET request with some headers.
+    fn get<'a>(&'a self, url: &'a str, headers: &'a [(&'a str, &'a str)]) -> BoxFuture<'a, Result<Response, Error>>;
+}
+
+/// An [HttpClient] sending real requests.
+pub(crate) struct ReqwestClient {
+    client: reqwest::Client,
+}
+
+impl ReqwestClient {
+    pub(crate) fn new(timeout: Duration, redirect: Policy, proxy: Option<Proxy>) -> ReqwestClient {
+        let mut builder = reqwest::Client::builder()
+            .timeout(timeout)
+            .redirect(redirect);
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(proxy);
+        }
+
+        ReqwestClient {
+            client: builder.build().expect("Failed to build the HTTP client"),
+        }
+    }
+}
+
+impl HttpClient for ReqwestClient {
+    fn get<'a>(&'a self, url: &'a str, headers: &'a [(&'a str, &'a str)]) -> BoxFuture<'a, Result<Response, Error>> {
+        Box::pin(async move {
+            let mut request = self.client.get(url);
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            let response = request.send().await.map_err(|e| Error::Network(e.to_string()))?;
+
+            let status = response.status().as_u16();
+            let url = response.url().to_string();
+            let headers = response.headers()
+                .iter()
+                .filter_map(|(name, value)| value.to_str().ok().map(|value| (name.as_str().to_string(), value.to_string())))
+                .collect();
+            let body = response.text().await.map_err(|e| Error::Network(e.to_string()))?;
+            Ok(Response {
+                status,
+                url,
+                headers,
+                body,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod fake {
+    use std::{collections::HashMap, sync::Mutex};
+    use futures::future;
+    use super::*;
+
+    /// A requested URL with the headers of the request
+    type Request = (String, Vec<(String, String)>);
+
+    /// An [HttpClient] answering with the pages it has been given, and failing for the other URLs.
+    pub(crate) struct FakeClient {
+        responses: HashMap<String, Response>,
+        redirections: HashMap<String, String>,
+        max_redirects: usize,
+        requests: Mutex<Vec<Request>>,
+    }
+
+    impl FakeClient {
+        /// A client following up to 10 redirections, like the default policy of reqwest.
+        pub(crate) fn new() -> FakeClient {
+            FakeClient {
+                responses: HashMap::new(),
+                redirections: HashMap::new(),
+                max_redirects: 10,
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+
+        pub(crate) fn page(mut self, url: &str, status: u16, body: &str) -> FakeClient {
+            self.responses.insert(url.to_string(), Response {
+                status,
+                url: url.to_string(),
+                headers: Vec::new(),
+                body: body.to_string(),
+            });
+            self
+        }
+
+        /// Add a header to the response to `url`, which must have been given before.
+        pub(crate) fn header(mut self, url: &str, name: &str, value: &str) -> FakeClient {
+            let response = self.responses.get_mut(url).expect("No page for this URL");
+            response.headers.push((name.to_string(), value.to_string()));
+            self
+        }
+
+        pub(crate) fn redirection(mut self, from: &str, to: &str) -> FakeClient {
+            self.redirections.insert(from.to_string(), to.to_string());
+            self
+        }
+
+        /// Fail the requests redirected more than `max_redirects` times, like `Policy::limited`.
+        pub(crate) fn max_redirects(mut self, max_redirects: usize) -> FakeClient {
+            self.max_redirects = max_redirects;
+            self
+        }
+
+        /// The URLs requested so far, with the headers of the requests.
+        pub(crate) fn requests(&self) -> Vec<Request> {
+            self.requests.lock().unwrap().clone()
+        }
+
+        fn answer(&self, url: &str) -> Result<Response, Error> {
+            let mut url = url;
+            let mut redirects = 0;
+            while let Some(target) = self.redirections.get(url) {
+                if redirects == self.max_redirects {
+                    return Err(Error::Network(format!("too many redirections from {}", url)));
+                }
+                redirects += 1;
+                url = target;
+            }
+            self.responses.get(url).cloned().ok_or_else(|| Error::Network(format!("connection refused to {}", url)))
+        }
+    }
+
+    impl HttpClient for FakeClient {
+        fn get<'a>(&'a self, url: &'a str, headers: &'a [(&'a str, &'a str)]) -> BoxFuture<'a, Result<Response, Error>> {
+            let headers = headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+            self.requests.lock().unwrap().push((url.to_string(), headers));
+            Box::pin(future::ready(self.answer(url)))
+        }
+    }
+}
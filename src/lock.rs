@@ -0,0 +1,56 @@
+use std::fs::{File, OpenOptions};
+use std::process::exit;
+use crate::config::Config;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LockKind {
+    /// Held by processes writing the database. Excludes any other process.
+    Exclusive,
+    /// Held by processes only reading the database. Excludes writers only.
+    Shared,
+}
+
+/// An advisory lock on the database, released when dropped (or when the process dies).
+pub(crate) struct DatabaseLock {
+    file: File,
+}
+
+impl DatabaseLock {
+    /// Lock the database, waiting for the running instance to release it if `wait` is true.
+    /// Exits if the database is locked and `wait` is false.
+    pub(crate) fn acquire(config: &Config, kind: LockKind, wait: bool) -> DatabaseLock {
+        let path = format!("{}.lock", config.database_file);
+        let file = match OpenOptions::new().create(true).truncate(false).write(true).open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("Failed to open the lock file {}: {}", path, e);
+                exit(1);
+            }
+        };
+
+        let result = match (kind, wait) {
+            (LockKind::Exclusive, true) => fs2::FileExt::lock_exclusive(&file),
+            (LockKind::Exclusive, false) => fs2::FileExt::try_lock_exclusive(&file),
+            (LockKind::Shared, true) => fs2::FileExt::lock_shared(&file),
+            (LockKind::Shared, false) => fs2::FileExt::try_lock_shared(&file),
+        };
+
+        match result {
+            Ok(()) => DatabaseLock { file },
+            Err(e) if e.kind() == fs2::lock_contended_error().kind() => {
+                eprintln!("The database {} is used by another instance. Use --wait to wait for it to finish.", config.database_file);
+                exit(1);
+            }
+            Err(e) => {
+                eprintln!("Failed to lock the database {}: {}", config.database_file, e);
+                exit(1);
+            }
+        }
+    }
+}
+
+impl Drop for DatabaseLock {
+    fn drop(&mut self) {
+        let _ = fs2::FileExt::unlock(&self.file);
+    }
+}
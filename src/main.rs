@@ -2,18 +2,37 @@ use clap::*;
 mod stats;
 mod crawler;
 mod config;
-mod google;
+mod discovery;
 mod gleam;
 mod meilisearch;
 mod database;
+mod lock;
+mod backup;
+mod queue;
+mod rate_limiter;
+mod scheduler;
+mod shutdown;
+mod refresh;
+mod seen;
+mod robots;
+mod extract;
+mod shorteners;
+mod retry;
+mod proxies;
+mod http;
+mod hosts;
+mod sitemaps;
+mod feeds;
+mod util;
+mod xml;
 use config::*;
 use stats::*;
-use crawler::launch;
+use crawler::{launch, ingest};
 use meilisearch::init_meilisearch;
-
-fn backup() {
-
-}
+use database::open_database;
+use lock::{DatabaseLock, LockKind};
+use backup::{backup_command, restore_command};
+use hosts::hosts_command;
 
 fn configurate() {
 
@@ -26,8 +45,13 @@ async fn main() {
         (author: "Mubelotix <mubelotix@gmail.com>")
         (about: "Crawl the web to find gleam.io links")
         (@arg CONFIG: -c --config +takes_value "Sets a custom config file")
+        (@arg WAIT: -w --wait "Wait for the running instance to release the database instead of exiting")
         (@subcommand stats =>
             (about: "Display stats about the database")
+            (@arg GIVEAWAY: "Display the pages referring to the giveaway with this key instead")
+        )
+        (@subcommand hosts =>
+            (about: "Display the yield of the hosts of the result pages and the blocked ones")
         )
         (@subcommand init_meilisearch =>
             (about: "Init the meilisearch index")
@@ -38,23 +62,54 @@ async fn main() {
         (@subcommand backup =>
             (about: "Backup the database")
         )
+        (@subcommand restore =>
+            (about: "List the backups of the database, or restore one of them")
+            (@arg SNAPSHOT: "The timestamp or file name of the backup to restore")
+        )
         (@subcommand launch =>
             (about: "Launch the bot")
-            (@arg fast: -f --f "Do not load gleam.io pages and do not save them")
+            (@arg fast: -f --fast "Do not load gleam.io pages, save the links to the queue file instead")
+        )
+        (@subcommand ingest =>
+            (about: "Load the links saved by fast runs")
+            (@arg QUEUE: "Sets a custom queue file")
         )
     ).get_matches();
 
     let config = read_config(matches.value_of("CONFIG").unwrap_or("config.toml"));
+    let wait = matches.is_present("WAIT");
 
     match matches.subcommand() {
-        ("stats", Some(_args)) => stats(config),
-        ("init_meilisearch", Some(_args)) => init_meilisearch(&config).await,
+        ("stats", Some(args)) => {
+            let _lock = DatabaseLock::acquire(&config, LockKind::Shared, wait);
+            stats(config, args.value_of("GIVEAWAY"));
+        },
+        ("hosts", Some(_args)) => {
+            let _lock = DatabaseLock::acquire(&config, LockKind::Shared, wait);
+            hosts_command(&config);
+        },
+        ("init_meilisearch", Some(_args)) => {
+            let _lock = DatabaseLock::acquire(&config, LockKind::Shared, wait);
+            init_meilisearch(open_database(&config).as_ref(), &config).await;
+        },
         ("configurate", Some(_args)) => configurate(),
-        ("backup", Some(_args)) => backup(),
+        ("backup", Some(_args)) => {
+            let _lock = DatabaseLock::acquire(&config, LockKind::Exclusive, wait);
+            backup_command(&config);
+        },
+        ("restore", Some(args)) => {
+            let _lock = DatabaseLock::acquire(&config, LockKind::Exclusive, wait);
+            restore_command(&config, args.value_of("SNAPSHOT"));
+        },
         ("launch", Some(args)) => {
-            let fast: bool = args.value_of("fast").unwrap_or("false").parse().unwrap();
+            let fast = args.is_present("fast");
+            let _lock = DatabaseLock::acquire(&config, LockKind::Exclusive, wait);
             launch(config, fast).await;
         },
+        ("ingest", Some(args)) => {
+            let _lock = DatabaseLock::acquire(&config, LockKind::Exclusive, wait);
+            ingest(config, args.value_of("QUEUE")).await;
+        },
         (name, Some(_args)) => {
             println!("Unknown subcommand: {:?}", name);
         }
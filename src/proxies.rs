@@ -0,0 +1,250 @@
+use std::{collections::HashMap, sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}}, time::{Duration, Instant}};
+use reqwest::{Proxy, redirect::Policy};
+use crate::{config::{Config, ProxyRotation}, http::{HttpClient, ReqwestClient}};
+
+/// The clients sending requests through one proxy, or directly.
+#[derive(Clone)]
+pub(crate) struct HttpClients {
+    pub(crate) client: Arc<dyn HttpClient>,
+    /// Client following a limited number of redirections, to expand short links
+    pub(crate) redirect_client: Arc<dyn HttpClient>,
+}
+
+impl HttpClients {
+    fn new(config: &Config, proxy: Option<Proxy>) -> HttpClients {
+        let timeout = Duration::from_secs(config.timeout as u64);
+        HttpClients {
+            client: Arc::new(ReqwestClient::new(timeout, Policy::default(), proxy.clone())),
+            redirect_client: Arc::new(ReqwestClient::new(timeout, Policy::limited(config.max_redirects), proxy)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ProxyHealth {
+    consecutive_failures: u32,
+    failures: u64,
+    successes: u64,
+    /// The proxy is not used until then
+    evicted_until: Option<Instant>,
+}
+
+impl ProxyHealth {
+    fn is_evicted(&self) -> bool {
+        matches!(self.evicted_until, Some(evicted_until) if Instant::now() < evicted_until)
+    }
+}
+
+struct ProxyState {
+    url: String,
+    clients: HttpClients,
+    health: Mutex<ProxyHealth>,
+}
+
+/// The configured proxies, among which the requests are spread.
+/// Proxies failing to connect too many times in a row are evicted until their cool-off has passed.
+/// Once all of them are, requests are sent directly only if the direct fallback is enabled.
+pub(crate) struct ProxyPool {
+    direct: HttpClients,
+    proxies: Vec<ProxyState>,
+    rotation: ProxyRotation,
+    max_failures: u32,
+    cooloff: Duration,
+    direct_fallback: bool,
+    next: AtomicUsize,
+    sticky: Mutex<HashMap<String, usize>>,
+    newly_evicted: Mutex<Vec<String>>,
+}
+
+impl ProxyPool {
+    pub(crate) fn new(config: &Config) -> ProxyPool {
+        let proxies = config.proxy.urls.iter().map(|url| {
+            let proxy = match Proxy::all(url.as_str()) {
+                Ok(proxy) => proxy,
+                Err(e) => panic!("Invalid proxy {} in the configuration file: {}", url, e),
+            };
+            ProxyState {
+                url: url.clone(),
+                clients: HttpClients::new(config, Some(proxy)),
+                health: Mutex::new(ProxyHealth::default()),
+            }
+        }).collect();
+
+        ProxyPool {
+            direct: HttpClients::new(config, None),
+            proxies,
+            rotation: config.proxy.rotation,
+            max_failures: config.proxy.max_failures,
+            cooloff: Duration::from_secs(config.proxy.cooloff),
+            direct_fallback: config.proxy.direct_fallback,
+            next: AtomicUsize::new(0),
+            sticky: Mutex::new(HashMap::new()),
+            newly_evicted: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn is_alive(&self, index: usize) -> bool {
+        !self.proxies[index].health.lock().unwrap().is_evicted()
+    }
+
+    /// The next proxy alive after the rotation counter.
+    fn round_robin(&self) -> Option<usize> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..self.proxies.len()).map(|offset| (start + offset) % self.proxies.len()).find(|index| self.is_alive(*index))
+    }
+
+    /// Choose the proxy to use for a request to `host`.
+    /// Returns the index of the proxy, to be given to [ProxyPool::report], and its clients,
+    /// or `None` if all the proxies are evicted and the request should not be sent.
+    pub(crate) fn pick(&self, host: &str) -> Option<(Option<usize>, HttpClients)> {
+        if self.proxies.is_empty() {
+            return Some((None, self.direct.clone()));
+        }
+        let index = match self.rotation {
+            ProxyRotation::RoundRobin => self.round_robin(),
+            ProxyRotation::Sticky => {
+                let mut sticky = self.sticky.lock().unwrap();
+                match sticky.get(host) {
+                    Some(index) if self.is_alive(*index) => Some(*index),
+                    _ => {
+                        let index = self.round_robin();
+                        if let Some(index) = index {
+                            sticky.insert(host.to_string(), index);
+                        }
+                        index
+                    }
+                }
+            },
+            ProxyRotation::LeastFailed => {
+                // Rotate among the proxies with the fewest failures
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                (0..self.proxies.len())
+                    .map(|offset| (start + offset) % self.proxies.len())
+                    .filter_map(|index| {
+                        let health = self.proxies[index].health.lock().unwrap();
+                        if health.is_evicted() { None } else { Some((health.failures, index)) }
+                    })
+                    .min_by_key(|(failures, _index)| *failures)
+                    .map(|(_failures, index)| index)
+            },
+        };
+
+        match index {
+            Some(index) => Some((Some(index), self.proxies[index].clients.clone())),
+            None if self.direct_fallback => Some((None, self.direct.clone())),
+            None => None,
+        }
+    }
+
+    /// Record whether a request sent through a proxy reached its destination.
+    /// Only connection failures count, since the proxy is not to blame for the errors of the destination.
+    pub(crate) fn report(&self, proxy: Option<usize>, success: bool) {
+        let proxy = match proxy {
+            Some(index) => &self.proxies[index],
+            None => return,
+        };
+        let mut health = proxy.health.lock().unwrap();
+        if success {
+            health.successes += 1;
+            health.consecutive_failures = 0;
+            return;
+        }
+        health.failures += 1;
+        health.consecutive_failures += 1;
+        if health.consecutive_failures >= self.max_failures && !health.is_evicted() {
+            self.newly_evicted.lock().unwrap().push(format!(
+                "{} after {} consecutive failures ({} successes, {} failures)",
+                proxy.url, health.consecutive_failures, health.successes, health.failures
+            ));
+            // It is evicted again after a single failure if it still fails after its cool-off
+            health.consecutive_failures = self.max_failures.saturating_sub(1);
+            health.evicted_until = Some(Instant::now() + self.cooloff);
+        }
+    }
+
+    /// Describe the proxies evicted since the last call.
+    pub(crate) fn take_evicted(&self) -> Vec<String> {
+        std::mem::take(&mut *self.newly_evicted.lock().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy_pool(rotation: &str, options: &str) -> ProxyPool {
+        let config: Config = toml::from_str(&format!(
+            "[proxy]\nurls = [\"http://127.0.0.1:8080\", \"socks5://127.0.0.1:1080\", \"http://127.0.0.1:8081\"]\nrotation = \"{}\"\nmax_failures = 2\n{}",
+            rotation, options
+        )).unwrap();
+        ProxyPool::new(&config)
+    }
+
+    fn pick(pool: &ProxyPool, host: &str) -> Option<Option<usize>> {
+        pool.pick(host).map(|(proxy, _clients)| proxy)
+    }
+
+    #[test]
+    fn rotation() {
+        let pool = proxy_pool("round_robin", "");
+        let picked = (0..4).map(|_| pick(&pool, "example.com")).collect::<Vec<_>>();
+        assert_eq!(picked, vec![Some(Some(0)), Some(Some(1)), Some(Some(2)), Some(Some(0))]);
+
+        let pool = proxy_pool("sticky", "");
+        assert_eq!(pick(&pool, "example.com"), Some(Some(0)));
+        assert_eq!(pick(&pool, "example.org"), Some(Some(1)));
+        assert_eq!(pick(&pool, "example.com"), Some(Some(0)));
+
+        let pool = proxy_pool("least_failed", "");
+        pool.report(Some(0), false);
+        pool.report(Some(0), true);
+        pool.report(Some(1), false);
+        assert_eq!(pick(&pool, "example.com"), Some(Some(2)));
+        assert_eq!(pick(&pool, "example.com"), Some(Some(2)));
+
+        // Without proxies, the requests are sent directly
+        let pool = ProxyPool::new(&toml::from_str("").unwrap());
+        assert_eq!(pick(&pool, "example.com"), Some(None));
+    }
+
+    #[test]
+    fn eviction() {
+        let pool = proxy_pool("round_robin", "");
+        pool.report(Some(1), false);
+        pool.report(Some(1), true);
+        pool.report(Some(1), false);
+        assert!(pool.take_evicted().is_empty());
+        pool.report(Some(1), false);
+        assert_eq!(pool.take_evicted().len(), 1);
+        let picked = (0..4).map(|_| pick(&pool, "example.com")).collect::<Vec<_>>();
+        assert_eq!(picked, vec![Some(Some(0)), Some(Some(2)), Some(Some(2)), Some(Some(0))]);
+
+        // Requests are not sent once all the proxies are evicted, unless the direct fallback is enabled
+        for index in &[0, 2] {
+            pool.report(Some(*index), false);
+            pool.report(Some(*index), false);
+        }
+        assert_eq!(pick(&pool, "example.com"), None);
+
+        let pool = proxy_pool("round_robin", "direct_fallback = true");
+        for index in 0..3 {
+            pool.report(Some(index), false);
+            pool.report(Some(index), false);
+        }
+        assert_eq!(pick(&pool, "example.com"), Some(None));
+    }
+
+    #[test]
+    fn cooloff() {
+        let pool = proxy_pool("round_robin", "cooloff = 0");
+        pool.report(Some(1), false);
+        pool.report(Some(1), false);
+        assert_eq!(pool.take_evicted().len(), 1);
+        let picked = (0..3).map(|_| pick(&pool, "example.com")).collect::<Vec<_>>();
+        assert_eq!(picked, vec![Some(Some(0)), Some(Some(1)), Some(Some(2))]);
+
+        // A proxy still failing after its cool-off is evicted again at once
+        pool.report(Some(1), false);
+        assert_eq!(pool.take_evicted().len(), 1);
+    }
+}
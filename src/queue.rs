@@ -0,0 +1,137 @@
+use std::{collections::HashMap, fs, io, time::{Duration, Instant}};
+use serde::{Serialize, Deserialize};
+use crate::{gleam::get_gleam_id, database::Referer, util::atomic_write};
+
+/// The queue is saved after this many changes, or when it was last saved this long ago.
+const SAVE_EVERY: usize = 50;
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A gleam link discovered but not loaded yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct QueuedLink {
+    pub(crate) url: String,
+    /// The pages the link has been found on
+    pub(crate) referers: Vec<Referer>,
+}
+
+/// The pending work of the crawler, saved on disk so that it can be resumed after a restart
+/// or done by another run (links found in fast mode).
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Queue {
+    #[serde(skip)]
+    path: String,
+    /// Result pages to resolve
+    pub(crate) results: Vec<String>,
+    /// Gleam links to load
+    pub(crate) links: Vec<QueuedLink>,
+    /// Number of times the queued results and links failed to load
+    #[serde(default)]
+    failures: HashMap<String, u32>,
+    /// Number of changes since the last save
+    #[serde(skip)]
+    unsaved_changes: usize,
+    #[serde(skip)]
+    saved_at: Option<Instant>,
+}
+
+impl Queue {
+    pub(crate) fn open(path: &str) -> Queue {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => panic!("Failed to read the queue file {}: {}", path, e),
+        };
+        let mut queue: Queue = if content.trim().is_empty() {
+            Queue::default()
+        } else {
+            serde_json::from_str(&content).expect("Failed to parse the queue file")
+        };
+        queue.path = path.to_string();
+        queue.saved_at = Some(Instant::now());
+        queue
+    }
+
+    pub(crate) fn save(&mut self) {
+        let data = serde_json::to_string(self).expect("Can't serialize queue");
+        atomic_write(&self.path, data.as_bytes()).expect("Can't write data to queue");
+        self.unsaved_changes = 0;
+        self.saved_at = Some(Instant::now());
+    }
+
+    /// Record a change and save the queue if enough changes happened since the last save.
+    /// A crash loses at most these changes, so `save` must still be called once the work is done.
+    pub(crate) fn checkpoint(&mut self) {
+        self.unsaved_changes += 1;
+        let outdated = self.saved_at.is_none_or(|saved_at| saved_at.elapsed() >= SAVE_INTERVAL);
+        if self.unsaved_changes >= SAVE_EVERY || outdated {
+            self.save();
+        }
+    }
+
+    pub(crate) fn add_result(&mut self, url: String) {
+        if !self.results.contains(&url) {
+            self.results.push(url);
+        }
+    }
+
+    pub(crate) fn remove_result(&mut self, url: &str) {
+        self.results.retain(|result| result != url);
+        self.failures.remove(url);
+    }
+
+    /// Count a failure to load a queued result or link and return how many times it failed.
+    pub(crate) fn record_failure(&mut self, url: &str) -> u32 {
+        let failures = self.failures.entry(url.to_string()).or_default();
+        *failures += 1;
+        *failures
+    }
+
+    /// Add a link, or add its referers to the queued link to the same giveaway.
+    /// Returns false if the giveaway was already queued or if the link is invalid.
+    pub(crate) fn add_link(&mut self, link: QueuedLink) -> bool {
+        let key = match get_gleam_id(&link.url) {
+            Some(key) => key,
+            None => return false,
+        };
+        if let Some(queued) = self.links.iter_mut().find(|queued| get_gleam_id(&queued.url) == Some(key)) {
+            for referer in link.referers {
+                Referer::merge_into(&mut queued.referers, referer);
+            }
+            return false;
+        }
+        self.links.push(link);
+        true
+    }
+
+    pub(crate) fn remove_link(&mut self, url: &str) {
+        self.links.retain(|link| link.url != url);
+        self.failures.remove(url);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batched_saves() {
+        let path = std::env::temp_dir().join(format!("queue-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut queue = Queue::open(path);
+        queue.add_result(String::from("https://example.com/giveaways"));
+        queue.checkpoint();
+        assert!(Queue::open(path).results.is_empty());
+
+        for i in 1..SAVE_EVERY {
+            queue.add_result(format!("https://example.com/giveaways/{}", i));
+            queue.checkpoint();
+        }
+        assert_eq!(Queue::open(path).results.len(), SAVE_EVERY);
+
+        queue.remove_result("https://example.com/giveaways");
+        queue.checkpoint();
+        queue.save();
+        assert_eq!(Queue::open(path).results.len(), SAVE_EVERY - 1);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,69 @@
+use std::{collections::HashMap, sync::Mutex, time::{Duration, Instant}};
+use tokio::time::delay_until;
+
+/// Spaces the requests sent to each host by a cooldown.
+/// It can be shared between concurrent tasks: each call to [RateLimiter::wait] reserves the next free slot of the host.
+pub(crate) struct RateLimiter {
+    cooldown: Duration,
+    host_cooldowns: Mutex<HashMap<String, Duration>>,
+    next_requests: Mutex<HashMap<String, Instant>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(cooldown: Duration) -> RateLimiter {
+        RateLimiter {
+            cooldown,
+            host_cooldowns: Mutex::new(HashMap::new()),
+            next_requests: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Wait until a request can be sent to `host`.
+    pub(crate) async fn wait(&self, host: &str) {
+        let cooldown = self.host_cooldowns.lock().unwrap().get(host).copied().unwrap_or(self.cooldown);
+        let slot = {
+            let mut next_requests = self.next_requests.lock().unwrap();
+            let now = Instant::now();
+            let slot = match next_requests.get(host) {
+                Some(next_request) if *next_request > now => *next_request,
+                _ => now,
+            };
+            next_requests.insert(host.to_string(), slot + cooldown);
+            slot
+        };
+        delay_until(slot.into()).await;
+    }
+
+    /// Use a longer cooldown for `host`, as asked by its robots.txt file.
+    /// The default cooldown is kept if it is longer.
+    pub(crate) fn set_cooldown(&self, host: &str, cooldown: Duration) {
+        let mut host_cooldowns = self.host_cooldowns.lock().unwrap();
+        if cooldown > self.cooldown {
+            host_cooldowns.insert(host.to_string(), cooldown);
+        } else {
+            host_cooldowns.remove(host);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn cooldown_per_host() {
+        let rate_limiter = RateLimiter::new(Duration::from_millis(100));
+        let start = Instant::now();
+        rate_limiter.wait("example.com").await;
+        rate_limiter.wait("example.org").await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        futures::join!(rate_limiter.wait("example.com"), rate_limiter.wait("example.com"));
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        rate_limiter.set_cooldown("example.org", Duration::from_millis(400));
+        rate_limiter.wait("example.org").await;
+        rate_limiter.wait("example.org").await;
+        assert!(start.elapsed() >= Duration::from_millis(600));
+    }
+}
@@ -0,0 +1,130 @@
+use format::giveaway::SearchResult;
+
+const HOUR: f64 = 3600.0;
+
+/// The final refreshes of ended giveaways take at most this share of the refreshes, unless there are not enough running giveaways.
+const FINAL_REFRESH_SHARE: f64 = 0.25;
+
+/// Score the interest of refreshing a giveaway, the higher the sooner.
+/// Returns None for ended giveaways which have already been refreshed after their end.
+///
+/// Running giveaways are scored by the time since their last refresh, weighted by how soon they end
+/// and by how fast their entry count grows. Ended giveaways get one final refresh, before the running giveaways.
+fn score(now: u64, ends_at: u64, last_updated: u64, entry_evolution: &[(u64, f64)]) -> Option<f64> {
+    let staleness = now.saturating_sub(last_updated) as f64 / HOUR;
+
+    if ends_at <= now {
+        if last_updated > ends_at {
+            return None;
+        }
+        return Some(1_000_000.0 + staleness);
+    }
+
+    // Close to 1 for giveaways ending within the day, close to 0 for giveaways ending in months
+    let hours_left = (ends_at - now) as f64 / HOUR;
+    let urgency = 1.0 / (1.0 + hours_left / 24.0);
+
+    // Entries per hour between the two last measures
+    let growth = match entry_evolution {
+        [.., (time1, entries1), (time2, entries2)] if time2 > time1 => ((entries2 - entries1) / ((time2 - time1) as f64 / HOUR)).max(0.0),
+        _ => 0.0,
+    };
+
+    Some(staleness * (1.0 + 10.0 * urgency + (1.0 + growth).ln()))
+}
+
+pub(crate) fn refresh_score(giveaway: &SearchResult, now: u64) -> Option<f64> {
+    let mut entry_evolution = giveaway
+        .entry_evolution
+        .as_ref()
+        .map(|evolution| evolution.iter().map(|(time, entries)| (*time, *entries as f64)).collect::<Vec<(u64, f64)>>())
+        .unwrap_or_default();
+    entry_evolution.sort_by_key(|(time, _entries)| *time);
+
+    score(now, giveaway.ends_at(), giveaway.last_updated, &entry_evolution)
+}
+
+/// Select the `count` giveaways whose refresh is the most valuable.
+/// The final refreshes are limited to [FINAL_REFRESH_SHARE] of them, so that a backlog of ended giveaways can't starve the running ones.
+pub(crate) fn select_giveaways_to_refresh(candidates: Vec<SearchResult>, count: usize, now: u64) -> Vec<SearchResult> {
+    let mut scored = candidates
+        .into_iter()
+        .filter_map(|giveaway| refresh_score(&giveaway, now).map(|score| (score, giveaway)))
+        .collect::<Vec<(f64, SearchResult)>>();
+    scored.sort_by(|(score1, _), (score2, _)| score2.partial_cmp(score1).unwrap_or(std::cmp::Ordering::Equal));
+    let (ended, running): (Vec<_>, Vec<_>) = scored.into_iter().partition(|(_score, giveaway)| giveaway.ends_at() <= now);
+
+    let final_refreshes = ((count as f64 * FINAL_REFRESH_SHARE).ceil() as usize).max(count.saturating_sub(running.len())).min(count);
+    ended
+        .into_iter()
+        .take(final_refreshes)
+        .chain(running)
+        .take(count)
+        .map(|(_score, giveaway)| giveaway)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const NOW: u64 = 1_600_000_000;
+    const H: u64 = 3600;
+
+    fn giveaway(key: &str, ends_at: u64, last_updated: u64) -> SearchResult {
+        serde_json::from_value(json!({
+            "key": key,
+            "name": key,
+            "starts_at": 0,
+            "ends_at": ends_at,
+            "entry_methods": [],
+            "incentive_name": key,
+            "last_updated": last_updated,
+            "referers": [],
+            "entry_count": null,
+            "entry_evolution": null,
+        })).unwrap()
+    }
+
+    fn keys(giveaways: &[SearchResult]) -> Vec<&str> {
+        giveaways.iter().map(|giveaway| giveaway.giveaway.campaign.key.as_str()).collect()
+    }
+
+    #[test]
+    fn ended_giveaways() {
+        assert!(score(NOW, NOW - H, NOW - 2 * H, &[]).unwrap() > score(NOW, NOW + H, NOW - 100 * H, &[]).unwrap());
+        assert_eq!(score(NOW, NOW - 2 * H, NOW - H, &[]), None);
+    }
+
+    #[test]
+    fn running_giveaways() {
+        // Just refreshed
+        assert_eq!(score(NOW, NOW + H, NOW, &[]), Some(0.0));
+        // Ending soon
+        assert!(score(NOW, NOW + H, NOW - H, &[]).unwrap() > score(NOW, NOW + 30 * 24 * H, NOW - H, &[]).unwrap());
+        // Growing
+        let growing = [(NOW - 3 * H, 100.0), (NOW - 2 * H, 1000.0)];
+        let stable = [(NOW - 3 * H, 100.0), (NOW - 2 * H, 100.0)];
+        assert!(score(NOW, NOW + 24 * H, NOW - 2 * H, &growing).unwrap() > score(NOW, NOW + 24 * H, NOW - 2 * H, &stable).unwrap());
+        // Stale
+        assert!(score(NOW, NOW + 24 * H, NOW - 10 * H, &stable).unwrap() > score(NOW, NOW + 24 * H, NOW - 2 * H, &stable).unwrap());
+    }
+
+    #[test]
+    fn final_refresh_share() {
+        let mut candidates = (0..10).map(|i| giveaway(&format!("ended{}", i), NOW - H, NOW - 2 * H - i * H)).collect::<Vec<_>>();
+        candidates.push(giveaway("running1", NOW + H, NOW - H));
+        candidates.push(giveaway("running2", NOW + 30 * 24 * H, NOW - H));
+        candidates.push(giveaway("running3", NOW + 30 * 24 * H, NOW));
+
+        // The ended giveaways outnumber the budget, but the running ones still get most of it
+        let selected = select_giveaways_to_refresh(candidates.clone(), 4, NOW);
+        assert_eq!(keys(&selected), vec!["ended9", "running1", "running2", "running3"]);
+
+        // The ended giveaways get the budget the running ones don't need
+        let selected = select_giveaways_to_refresh(candidates.clone(), 6, NOW);
+        assert_eq!(keys(&selected), vec!["ended9", "ended8", "ended7", "running1", "running2", "running3"]);
+        assert_eq!(select_giveaways_to_refresh(candidates, 20, NOW).len(), 13);
+    }
+}
@@ -0,0 +1,197 @@
+use std::{collections::HashMap, fmt, sync::{Mutex, atomic::{AtomicUsize, Ordering}}, time::{Duration, Instant}};
+use rand::Rng;
+use crate::config::RetryConfig;
+
+/// Why a request with retries did not succeed.
+#[derive(Debug)]
+pub(crate) enum Failure<E> {
+    /// The last error, after the retries allowed were exhausted or if it was not worth retrying
+    Error(E),
+    /// The host failed too many times recently and was not requested
+    CircuitOpen,
+    /// The process is stopping
+    Interrupted,
+    /// All the proxies are evicted and the direct fallback is disabled
+    NoProxy,
+}
+
+/// The errors of the requests sent with retries.
+pub(crate) trait RequestError {
+    /// Whether the error may not happen again if the request is retried.
+    fn is_transient(&self) -> bool;
+
+    /// Whether the request failed before reaching the server, which is the fault of the proxy it was sent through if there is one.
+    fn is_connection_error(&self) -> bool;
+}
+
+impl<E: fmt::Display> fmt::Display for Failure<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Error(e) => e.fmt(f),
+            Failure::CircuitOpen => write!(f, "the host failed too many times recently"),
+            Failure::Interrupted => write!(f, "interrupted"),
+            Failure::NoProxy => write!(f, "all the proxies are evicted"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct HostHealth {
+    consecutive_failures: u32,
+    /// Set while the circuit is open, until the next request allowed to probe the host
+    open_until: Option<Instant>,
+}
+
+/// Decides when failed requests are retried and stops requesting the hosts which keep failing.
+pub(crate) struct Retrier {
+    config: RetryConfig,
+    remaining_budget: AtomicUsize,
+    hosts: Mutex<HashMap<String, HostHealth>>,
+}
+
+impl Retrier {
+    pub(crate) fn new(config: RetryConfig) -> Retrier {
+        Retrier {
+            remaining_budget: AtomicUsize::new(config.budget),
+            hosts: Mutex::new(HashMap::new()),
+            config,
+        }
+    }
+
+    /// Restore the retry budget, at the start of each task run.
+    pub(crate) fn new_cycle(&self) {
+        self.remaining_budget.store(self.config.budget, Ordering::Relaxed);
+    }
+
+    /// Whether `host` may be requested.
+    /// Once its cool-off has passed, a single request probes the host: the circuit is closed if it succeeds and opened again if it fails.
+    /// The other requests wait for another cool-off, after which a new probe is allowed in case the previous one never finished.
+    pub(crate) fn allows(&self, host: &str) -> bool {
+        let mut hosts = self.hosts.lock().unwrap();
+        let health = match hosts.get_mut(host) {
+            Some(health) => health,
+            None => return true,
+        };
+        match health.open_until {
+            Some(open_until) if Instant::now() >= open_until => {
+                health.open_until = Some(Instant::now() + Duration::from_secs(self.config.breaker_cooloff));
+                true
+            },
+            Some(_) => false,
+            None => true,
+        }
+    }
+
+    pub(crate) fn success(&self, host: &str) {
+        self.hosts.lock().unwrap().remove(host);
+    }
+
+    /// Record a failure of `host`, and return true if it opened the circuit.
+    pub(crate) fn failure(&self, host: &str) -> bool {
+        let mut hosts = self.hosts.lock().unwrap();
+        let health = hosts.entry(host.to_string()).or_default();
+        health.consecutive_failures += 1;
+        // A host failing while its circuit is open, such as the probe, doesn't get more attempts
+        if health.open_until.is_some() || health.consecutive_failures >= self.config.breaker_threshold {
+            health.consecutive_failures = 0;
+            health.open_until = Some(Instant::now() + Duration::from_secs(self.config.breaker_cooloff));
+            return true;
+        }
+        false
+    }
+
+    /// The delay before retrying a request which failed `attempt` times,
+    /// or `None` if it should not be retried.
+    pub(crate) fn backoff(&self, attempt: u32) -> Option<Duration> {
+        if attempt >= self.config.max_attempts {
+            return None;
+        }
+        let reserved = self.remaining_budget.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |remaining| remaining.checked_sub(1));
+        if reserved.is_err() {
+            return None;
+        }
+
+        let delay = self.config.base_delay.saturating_mul(1 << attempt.saturating_sub(1).min(16)).min(self.config.max_delay);
+        // Full delays would make the requests failing together retry together
+        let jitter = rand::thread_rng().gen_range(0.5, 1.0);
+        Some(Duration::from_secs(delay).mul_f64(jitter))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> RetryConfig {
+        RetryConfig {
+            max_attempts: 4,
+            base_delay: 2,
+            max_delay: 5,
+            budget: 4,
+            breaker_threshold: 3,
+            breaker_cooloff: 600,
+            max_requeues: 3,
+        }
+    }
+
+    #[test]
+    fn backoff() {
+        let retrier = Retrier::new(config());
+        let delay = retrier.backoff(1).unwrap();
+        assert!(delay >= Duration::from_secs(1) && delay <= Duration::from_secs(2));
+        let delay = retrier.backoff(2).unwrap();
+        assert!(delay >= Duration::from_secs(2) && delay <= Duration::from_secs(4));
+        let delay = retrier.backoff(3).unwrap();
+        assert!(delay >= Duration::from_millis(2500) && delay <= Duration::from_secs(5));
+        assert!(retrier.backoff(4).is_none());
+
+        // The budget is shared by all the requests
+        assert!(retrier.backoff(1).is_some());
+        assert!(retrier.backoff(1).is_none());
+        retrier.new_cycle();
+        assert!(retrier.backoff(1).is_some());
+    }
+
+    #[test]
+    fn circuit_breaker() {
+        let retrier = Retrier::new(config());
+        assert!(!retrier.failure("example.com"));
+        assert!(!retrier.failure("example.com"));
+        retrier.success("example.com");
+        assert!(!retrier.failure("example.com"));
+        assert!(!retrier.failure("example.com"));
+        assert!(retrier.allows("example.com"));
+        assert!(retrier.failure("example.com"));
+        assert!(!retrier.allows("example.com"));
+        assert!(retrier.allows("example.org"));
+        retrier.success("example.com");
+        assert!(retrier.allows("example.com"));
+    }
+
+    #[test]
+    fn half_open_circuit() {
+        let retrier = Retrier::new(config());
+        let end_cooloff = |retrier: &Retrier| {
+            retrier.hosts.lock().unwrap().get_mut("example.com").unwrap().open_until = Some(Instant::now() - Duration::from_secs(1));
+        };
+        for _ in 0..3 {
+            retrier.failure("example.com");
+        }
+        assert!(!retrier.allows("example.com"));
+
+        // A single request probes the host after the cool-off, and its failure opens the circuit again
+        end_cooloff(&retrier);
+        assert!(retrier.allows("example.com"));
+        assert!(!retrier.allows("example.com"));
+        assert!(retrier.failure("example.com"));
+        assert!(!retrier.allows("example.com"));
+
+        // The circuit is closed once a probe succeeds
+        end_cooloff(&retrier);
+        assert!(retrier.allows("example.com"));
+        retrier.success("example.com");
+        assert!(retrier.allows("example.com"));
+        assert!(retrier.allows("example.com"));
+        assert!(!retrier.failure("example.com"));
+    }
+}
@@ -0,0 +1,239 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::Duration};
+use crate::http::HttpClient;
+use url::Url;
+
+/// Rules are fetched again after this many seconds.
+const ROBOTS_TTL: u64 = 24 * 3600;
+
+/// The rules of a robots.txt file that apply to our user agent.
+#[derive(Debug, Default)]
+pub(crate) struct Rules {
+    /// Path patterns and whether they are allowed
+    rules: Vec<(String, bool)>,
+    pub(crate) crawl_delay: Option<Duration>,
+}
+
+impl Rules {
+    /// Parse a robots.txt file, keeping the group matching `agent` or the `*` group if no group matches.
+    pub(crate) fn parse(content: &str, agent: &str) -> Rules {
+        let agent = agent.to_lowercase();
+        let mut specific = Rules::default();
+        let mut generic = Rules::default();
+        let mut found_specific = false;
+
+        // Which groups the lines being read belong to
+        let mut in_specific = false;
+        let mut in_generic = false;
+        let mut reading_agents = false;
+
+        for line in content.lines() {
+            let line = match line.find('#') {
+                Some(idx) => &line[..idx],
+                None => line,
+            };
+            let (field, value) = match line.find(':') {
+                Some(idx) => (line[..idx].trim().to_lowercase(), line[idx + 1..].trim()),
+                None => continue,
+            };
+
+            if field == "user-agent" {
+                if !reading_agents {
+                    in_specific = false;
+                    in_generic = false;
+                    reading_agents = true;
+                }
+                let value = value.to_lowercase();
+                if value == "*" {
+                    in_generic = true;
+                } else if !value.is_empty() && agent.contains(&value) {
+                    in_specific = true;
+                    found_specific = true;
+                }
+                continue;
+            }
+            reading_agents = false;
+
+            let mut groups = Vec::new();
+            if in_specific {
+                groups.push(&mut specific);
+            }
+            if in_generic {
+                groups.push(&mut generic);
+            }
+            for group in groups {
+                match field.as_str() {
+                    "allow" if !value.is_empty() => group.rules.push((value.to_string(), true)),
+                    "disallow" if !value.is_empty() => group.rules.push((value.to_string(), false)),
+                    "crawl-delay" => {
+                        if let Ok(delay) = value.parse::<f64>() {
+                            if delay >= 0.0 {
+                                group.crawl_delay = Some(Duration::from_secs_f64(delay));
+                            }
+                        }
+                    },
+                    _ => (),
+                }
+            }
+        }
+
+        if found_specific {
+            specific
+        } else {
+            generic
+        }
+    }
+
+    /// Whether a path (including the query) may be loaded.
+    /// The longest matching rule wins, and allow rules win ties.
+    pub(crate) fn is_allowed(&self, path: &str) -> bool {
+        let mut best: Option<(usize, bool)> = None;
+        for (pattern, allow) in &self.rules {
+            if !matches(pattern, path) {
+                continue;
+            }
+            best = match best {
+                Some((length, best_allow)) if length > pattern.len() || (length == pattern.len() && best_allow) => Some((length, best_allow)),
+                _ => Some((pattern.len(), *allow)),
+            };
+        }
+        best.map(|(_, allow)| allow).unwrap_or(true)
+    }
+}
+
+/// Match a path against a robots.txt pattern supporting `*` and a trailing `$`.
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    if !path.starts_with(first) {
+        return false;
+    }
+    let mut rest = &path[first.len()..];
+    let parts = parts.collect::<Vec<&str>>();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+/// Fetch the rules of the host of `url`.
+/// The request identifies itself as `agent`, the user agent matched against the groups of the file.
+/// Returns `None` if the host is unreachable, in which case nothing should be loaded from it for now.
+pub(crate) async fn fetch(client: &dyn HttpClient, url: &Url, agent: &str) -> Option<Rules> {
+    let mut robots_url = url.clone();
+    robots_url.set_path("/robots.txt");
+    robots_url.set_query(None);
+    robots_url.set_fragment(None);
+
+    let response = client.get(robots_url.as_str(), &[("User-Agent", agent)]).await.ok()?;
+    if (200..300).contains(&response.status) {
+        Some(Rules::parse(&response.body, agent))
+    } else if (400..500).contains(&response.status) {
+        // No robots.txt file means that everything is allowed
+        Some(Rules::default())
+    } else {
+        None
+    }
+}
+
+/// The rules of the hosts visited recently.
+pub(crate) struct RobotsCache {
+    entries: Mutex<HashMap<String, (u64, Arc<Rules>)>>,
+}
+
+impl RobotsCache {
+    pub(crate) fn new() -> RobotsCache {
+        RobotsCache {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn get(&self, host: &str, now: u64) -> Option<Arc<Rules>> {
+        let entries = self.entries.lock().unwrap();
+        match entries.get(host) {
+            Some((fetched_at, rules)) if fetched_at + ROBOTS_TTL > now => Some(Arc::clone(rules)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn insert(&self, host: String, rules: Arc<Rules>, now: u64) {
+        self.entries.lock().unwrap().insert(host, (now, rules));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::fake::FakeClient;
+
+    const ROBOTS: &str = "
+# Comment
+User-agent: *
+Disallow: /private/
+Allow: /private/public
+Disallow: /*.pdf$
+Crawl-delay: 5
+
+User-agent: BadBot
+User-agent: gleam_finder
+Disallow: /giveaways # no giveaways for us
+Crawl-delay: 0.5
+";
+
+    #[tokio::test]
+    async fn fetching() {
+        let client = FakeClient::new()
+            .page("https://example.com/robots.txt", 200, ROBOTS)
+            .page("https://example.org/robots.txt", 404, "Not found")
+            .page("https://example.net/robots.txt", 500, "Internal server error");
+
+        let url = Url::parse("https://example.com/giveaways/1?page=2").unwrap();
+        let rules = fetch(&client, &url, "gleam_finder").await.unwrap();
+        assert!(!rules.is_allowed("/giveaways/1"));
+        assert_eq!(client.requests()[0].0, "https://example.com/robots.txt");
+        assert_eq!(client.requests()[0].1, vec![(String::from("User-Agent"), String::from("gleam_finder"))]);
+
+        let url = Url::parse("https://example.org/giveaways/1").unwrap();
+        assert!(fetch(&client, &url, "gleam_finder").await.unwrap().is_allowed("/giveaways/1"));
+        let url = Url::parse("https://example.net/giveaways/1").unwrap();
+        assert!(fetch(&client, &url, "gleam_finder").await.is_none());
+    }
+
+    #[test]
+    fn groups() {
+        let rules = Rules::parse(ROBOTS, "other_bot");
+        assert_eq!(rules.crawl_delay, Some(Duration::from_secs(5)));
+        assert!(!rules.is_allowed("/private/page"));
+        assert!(rules.is_allowed("/private/public/page"));
+        assert!(rules.is_allowed("/giveaways/1"));
+
+        let rules = Rules::parse(ROBOTS, "gleam_finder");
+        assert_eq!(rules.crawl_delay, Some(Duration::from_millis(500)));
+        assert!(rules.is_allowed("/private/page"));
+        assert!(!rules.is_allowed("/giveaways/1"));
+
+        let rules = Rules::parse("", "gleam_finder");
+        assert!(rules.is_allowed("/anything"));
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(matches("/", "/page"));
+        assert!(matches("/*.pdf$", "/files/doc.pdf"));
+        assert!(!matches("/*.pdf$", "/files/doc.pdf?download"));
+        assert!(matches("/*.pdf", "/files/doc.pdf?download"));
+        assert!(matches("/a*b*c", "/axxbyyc/end"));
+        assert!(!matches("/a*b*c", "/axxcyyb"));
+        assert!(matches("/page$", "/page"));
+        assert!(!matches("/page$", "/pages"));
+    }
+}
@@ -0,0 +1,218 @@
+use std::str::FromStr;
+use chrono::{TimeZone, Utc};
+use crate::config::{OverrunPolicy, ScheduleConfig, TaskSchedule};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Task {
+    /// Search new result pages
+    Discovery,
+    /// Load the queued result pages and gleam links
+    Resolving,
+    /// Update the giveaways whose refresh is the most valuable
+    Refresh,
+    /// Send the changes to MeiliSearch
+    MeiliSearchSync,
+    Backup,
+}
+
+enum Schedule {
+    Interval(u64),
+    Cron(Box<cron::Schedule>),
+}
+
+impl Schedule {
+    /// The first slot strictly after `time`, for a task last planned at `time`.
+    /// Cron expressions limited to some years run out of slots.
+    fn next_slot(&self, time: u64) -> Option<u64> {
+        match self {
+            Schedule::Interval(interval) => Some(time + interval),
+            Schedule::Cron(schedule) => schedule
+                .after(&Utc.timestamp_opt(time as i64, 0).unwrap())
+                .next()
+                .map(|date| date.timestamp() as u64),
+        }
+    }
+}
+
+struct ScheduledTask {
+    task: Task,
+    schedule: Schedule,
+    overrun: OverrunPolicy,
+    /// The slot of the last run
+    planned: u64,
+    next_run: u64,
+}
+
+/// Decides when each task of the bot has to run.
+/// Every task runs once at startup and then follows its own schedule, until its schedule has no slot left.
+pub(crate) struct Scheduler {
+    tasks: Vec<ScheduledTask>,
+}
+
+impl Scheduler {
+    pub(crate) fn new(config: &ScheduleConfig, now: u64) -> Scheduler {
+        let tasks = vec![
+            (Task::Discovery, &config.discovery),
+            (Task::Resolving, &config.resolving),
+            (Task::Refresh, &config.refresh),
+            (Task::MeiliSearchSync, &config.meilisearch),
+            (Task::Backup, &config.backup),
+        ];
+
+        Scheduler {
+            tasks: tasks.into_iter().map(|(task, task_schedule)| ScheduledTask {
+                task,
+                schedule: Scheduler::parse_schedule(task, task_schedule),
+                overrun: task_schedule.overrun,
+                planned: now,
+                next_run: now,
+            }).collect(),
+        }
+    }
+
+    fn parse_schedule(task: Task, task_schedule: &TaskSchedule) -> Schedule {
+        match (&task_schedule.cron, task_schedule.interval) {
+            (Some(expression), _) => match cron::Schedule::from_str(expression) {
+                Ok(schedule) if schedule.upcoming(Utc).next().is_none() => panic!("The cron expression of the task {:?} has no upcoming run", task),
+                Ok(schedule) => Schedule::Cron(Box::new(schedule)),
+                Err(e) => panic!("Invalid cron expression for the task {:?}: {}", task, e),
+            },
+            (None, Some(interval)) if interval > 0 => Schedule::Interval(interval),
+            (None, _) => panic!("The task {:?} needs an interval or a cron expression", task),
+        }
+    }
+
+    /// Get the next task to run and the timestamp it has to run at, if any task is left.
+    /// Tasks due at the same time are returned in the order they are declared in [Task].
+    pub(crate) fn next(&self) -> Option<(Task, u64)> {
+        let mut next = self.tasks.first()?;
+        for task in &self.tasks[1..] {
+            if task.next_run < next.next_run {
+                next = task;
+            }
+        }
+        Some((next.task, next.next_run))
+    }
+
+    /// Plan the next run of a task which just finished.
+    /// If the run took longer than its interval, the missed slots are skipped or coalesced into a single immediate run.
+    /// A task whose schedule has no slot left is removed.
+    pub(crate) fn finished(&mut self, task: Task, now: u64) {
+        let index = match self.tasks.iter().position(|t| t.task == task) {
+            Some(index) => index,
+            None => return,
+        };
+        if Scheduler::plan(&mut self.tasks[index], now).is_none() {
+            eprintln!("The task {:?} has no upcoming run left and won't run anymore", task);
+            self.tasks.remove(index);
+        }
+    }
+
+    fn plan(scheduled: &mut ScheduledTask, now: u64) -> Option<()> {
+        let mut slot = scheduled.schedule.next_slot(scheduled.planned)?;
+        if slot > now {
+            scheduled.planned = slot;
+            scheduled.next_run = slot;
+            return Some(());
+        }
+
+        match scheduled.overrun {
+            OverrunPolicy::Skip => {
+                while slot <= now {
+                    slot = scheduled.schedule.next_slot(slot)?;
+                }
+                scheduled.planned = slot;
+                scheduled.next_run = slot;
+            },
+            OverrunPolicy::Coalesce => {
+                // The missed slot runs now even if it is the last one
+                let mut next_slot = scheduled.schedule.next_slot(slot);
+                while let Some(later_slot) = next_slot.filter(|next_slot| *next_slot <= now) {
+                    slot = later_slot;
+                    next_slot = scheduled.schedule.next_slot(slot);
+                }
+                scheduled.planned = slot;
+                scheduled.next_run = now;
+            },
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(overrun: OverrunPolicy) -> ScheduleConfig {
+        let schedule = TaskSchedule {
+            interval: Some(100),
+            cron: None,
+            overrun,
+        };
+        ScheduleConfig {
+            discovery: schedule.clone(),
+            resolving: schedule.clone(),
+            refresh: TaskSchedule {
+                interval: None,
+                cron: Some(String::from("0 0 * * * *")),
+                overrun,
+            },
+            meilisearch: schedule.clone(),
+            backup: schedule,
+        }
+    }
+
+    #[test]
+    fn order_and_intervals() {
+        let mut scheduler = Scheduler::new(&config(OverrunPolicy::Skip), 1000);
+        assert_eq!(scheduler.next(), Some((Task::Discovery, 1000)));
+        scheduler.finished(Task::Discovery, 1010);
+        assert_eq!(scheduler.next(), Some((Task::Resolving, 1000)));
+        scheduler.finished(Task::Resolving, 1020);
+        assert_eq!(scheduler.next(), Some((Task::Refresh, 1000)));
+        scheduler.finished(Task::Refresh, 1030);
+        scheduler.finished(Task::MeiliSearchSync, 1030);
+        scheduler.finished(Task::Backup, 1030);
+        assert_eq!(scheduler.next(), Some((Task::Discovery, 1100)));
+        scheduler.finished(Task::Discovery, 1100);
+        assert_eq!(scheduler.next(), Some((Task::Resolving, 1100)));
+    }
+
+    #[test]
+    fn cron() {
+        let mut scheduler = Scheduler::new(&config(OverrunPolicy::Skip), 3600 * 10 + 5);
+        scheduler.finished(Task::Refresh, 3600 * 10 + 6);
+        assert_eq!(scheduler.tasks[2].next_run, 3600 * 11);
+    }
+
+    #[test]
+    #[should_panic(expected = "has no upcoming run")]
+    fn expired_cron() {
+        let mut config = config(OverrunPolicy::Skip);
+        config.backup.cron = Some(String::from("0 0 0 1 1 * 2019"));
+        Scheduler::new(&config, 0);
+    }
+
+    #[test]
+    fn last_cron_slot() {
+        let mut scheduler = Scheduler::new(&config(OverrunPolicy::Skip), 1577664000);
+        scheduler.tasks[4].schedule = Schedule::Cron(Box::new(cron::Schedule::from_str("0 0 0 * * * 2019").unwrap()));
+        scheduler.finished(Task::Backup, 1577664000);
+        assert_eq!(scheduler.tasks[4].next_run, 1577750400);
+        scheduler.finished(Task::Backup, 1577750400);
+        assert!(scheduler.tasks.iter().all(|task| task.task != Task::Backup));
+    }
+
+    #[test]
+    fn overruns() {
+        let mut scheduler = Scheduler::new(&config(OverrunPolicy::Skip), 1000);
+        scheduler.finished(Task::Discovery, 1350);
+        assert_eq!(scheduler.tasks[0].next_run, 1400);
+
+        let mut scheduler = Scheduler::new(&config(OverrunPolicy::Coalesce), 1000);
+        scheduler.finished(Task::Discovery, 1350);
+        assert_eq!(scheduler.tasks[0].next_run, 1350);
+        scheduler.finished(Task::Discovery, 1360);
+        assert_eq!(scheduler.tasks[0].next_run, 1400);
+    }
+}
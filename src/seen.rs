@@ -0,0 +1,129 @@
+use std::{collections::HashMap, fs, io};
+use serde::{Serialize, Deserialize};
+use crate::util::atomic_write;
+
+/// Pages not visited and short links not expanded for this long are forgotten, in seconds.
+pub(crate) const RETENTION: u64 = 30 * 24 * 3600;
+
+/// What we know about a result page from our last visit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SeenPage {
+    pub(crate) visited_at: u64,
+    pub(crate) etag: Option<String>,
+    pub(crate) last_modified: Option<String>,
+    /// The gleam links found on the page
+    pub(crate) links: Vec<String>,
+    /// The gleam links embedded in a widget of the page
+    #[serde(default)]
+    pub(crate) embedded: Vec<String>,
+    /// The links to URL shorteners found on the page
+    #[serde(default)]
+    pub(crate) short_links: Vec<String>,
+    /// Whether the page was not loaded because robots.txt forbids it
+    #[serde(default)]
+    pub(crate) blocked: bool,
+}
+
+/// The result pages visited recently, so that they are not loaded again before their TTL expires.
+/// Once expired, they are loaded with a conditional request.
+pub(crate) struct SeenStore {
+    path: String,
+    pages: HashMap<String, SeenPage>,
+    default_ttl: u64,
+    host_ttls: HashMap<String, u64>,
+}
+
+impl SeenStore {
+    pub(crate) fn open(path: &str, default_ttl: u64, host_ttls: HashMap<String, u64>) -> SeenStore {
+        let pages = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).expect("Failed to parse the seen pages file"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => panic!("Failed to read the seen pages file {}: {}", path, e),
+        };
+
+        SeenStore {
+            path: path.to_string(),
+            pages,
+            default_ttl,
+            host_ttls,
+        }
+    }
+
+    /// The TTL of a host, configured for the host itself or for one of its parent domains.
+    fn ttl(&self, host: &str) -> u64 {
+        let mut domain = host;
+        loop {
+            if let Some(ttl) = self.host_ttls.get(domain) {
+                return *ttl;
+            }
+            match domain.find('.') {
+                Some(idx) => domain = &domain[idx + 1..],
+                None => return self.default_ttl,
+            }
+        }
+    }
+
+    pub(crate) fn get(&self, url: &str) -> Option<&SeenPage> {
+        self.pages.get(url)
+    }
+
+    /// Whether the page has been visited too recently to be loaded again.
+    pub(crate) fn is_fresh(&self, url: &str, host: &str, now: u64) -> bool {
+        match self.pages.get(url) {
+            Some(page) => page.visited_at + self.ttl(host) > now,
+            None => false,
+        }
+    }
+
+    /// The pages that robots.txt prevented us from loading.
+    pub(crate) fn blocked(&self) -> impl Iterator<Item = &str> {
+        self.pages.iter().filter(|(_url, page)| page.blocked).map(|(url, _page)| url.as_str())
+    }
+
+    pub(crate) fn insert(&mut self, url: String, page: SeenPage) {
+        self.pages.insert(url, page);
+    }
+
+    pub(crate) fn save(&mut self, now: u64) {
+        self.pages.retain(|_url, page| page.visited_at + RETENTION > now);
+
+        let data = serde_json::to_string(&self.pages).expect("Can't serialize seen pages");
+        atomic_write(&self.path, data.as_bytes()).expect("Can't write data to seen pages file");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ttls() {
+        let mut host_ttls = HashMap::new();
+        host_ttls.insert(String::from("example.com"), 100);
+        host_ttls.insert(String::from("news.example.org"), 10);
+        let mut store = SeenStore {
+            path: String::new(),
+            pages: HashMap::new(),
+            default_ttl: 1000,
+            host_ttls,
+        };
+        assert_eq!(store.ttl("www.example.com"), 100);
+        assert_eq!(store.ttl("example.com"), 100);
+        assert_eq!(store.ttl("news.example.org"), 10);
+        assert_eq!(store.ttl("example.org"), 1000);
+        assert_eq!(store.ttl("notexample.com"), 1000);
+
+        store.insert(String::from("https://www.example.com/"), SeenPage {
+            visited_at: 5000,
+            etag: None,
+            last_modified: None,
+            links: Vec::new(),
+            embedded: Vec::new(),
+            short_links: Vec::new(),
+            blocked: false,
+        });
+        assert!(store.is_fresh("https://www.example.com/", "www.example.com", 5099));
+        assert!(!store.is_fresh("https://www.example.com/", "www.example.com", 5100));
+        assert!(!store.is_fresh("https://www.example.com/other", "www.example.com", 5000));
+    }
+}
@@ -0,0 +1,172 @@
+use std::{collections::HashMap, fs, io, sync::Mutex};
+use serde::{Serialize, Deserialize};
+use url::Url;
+use crate::{extract::extract_gleam_links, http::{self, HttpClient}, seen::RETENTION, util::atomic_write};
+
+/// The URL shorteners and link pages expanded by default, in addition to the configured redirectors.
+pub(crate) const DEFAULT_SHORTENERS: &[&str] = &[
+    "bit.ly", "t.co", "goo.gl", "linktr.ee", "tinyurl.com", "ow.ly", "buff.ly", "is.gd", "rebrand.ly",
+    "cutt.ly", "shorturl.at", "tiny.cc", "lnkd.in", "fb.me", "dlvr.it", "t.ly", "rb.gy", "bl.ink",
+];
+
+/// Query parameters only used to track where the visitors come from.
+const TRACKING_PARAMETERS: &[&str] = &["fbclid", "gclid", "dclid", "msclkid", "igshid", "mc_cid", "mc_eid", "ref_src", "ref_url"];
+
+/// Whether `host` is a shortener or one of its subdomains.
+fn is_shortener(host: &str, redirectors: &[String]) -> bool {
+    DEFAULT_SHORTENERS.iter().copied().chain(redirectors.iter().map(|r| r.as_str())).any(|shortener| {
+        host == shortener || host.ends_with(&format!(".{}", shortener))
+    })
+}
+
+/// Keep the links of a page that point to shorteners, without `www.` and without their tracking parameters.
+/// The other parameters are kept, as redirectors often carry their target in the query string.
+pub(crate) fn short_links(urls: Vec<String>, redirectors: &[String]) -> Vec<String> {
+    let mut short_links = Vec::new();
+    for url in urls {
+        let mut url = match Url::parse(&url) {
+            Ok(url) => url,
+            Err(_) => continue,
+        };
+        let host = url.host_str().unwrap_or_default().to_lowercase();
+        let host = host.strip_prefix("www.").unwrap_or(&host).to_string();
+        if !is_shortener(&host, redirectors) || url.path().len() <= 1 {
+            continue;
+        }
+        if url.set_host(Some(&host)).is_err() {
+            continue;
+        }
+        let parameters = url
+            .query_pairs()
+            .filter(|(name, _value)| !name.starts_with("utm_") && !TRACKING_PARAMETERS.contains(&name.as_ref()))
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect::<Vec<_>>();
+        if parameters.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(parameters);
+        }
+        url.set_fragment(None);
+        let url = url.to_string();
+        if !short_links.contains(&url) {
+            short_links.push(url);
+        }
+    }
+    short_links
+}
+
+/// Follow the redirections of a short link and return the gleam links it leads to.
+/// The client is expected to follow a limited number of redirections.
+/// Link pages such as linktr.ee are not redirections, so the gleam links of the final page are collected too.
+pub(crate) async fn expand(client: &dyn HttpClient, url: &str) -> Result<Vec<String>, http::Error> {
+    let headers = [("User-Agent", "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:78.0) Gecko/20100101 Firefox/78.0")];
+    let response = client.get(url, &headers).await?.error_for_status()?;
+
+    let mut links = extract_gleam_links(&response.url);
+    for link in extract_gleam_links(&response.body) {
+        if !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    Ok(links)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Expansion {
+    expanded_at: u64,
+    links: Vec<String>,
+}
+
+/// The gleam links each short link led to.
+pub(crate) struct ShortenerCache {
+    path: String,
+    expansions: Mutex<HashMap<String, Expansion>>,
+}
+
+impl ShortenerCache {
+    pub(crate) fn open(path: &str) -> ShortenerCache {
+        let expansions = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).expect("Failed to parse the shortener cache file"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => panic!("Failed to read the shortener cache file {}: {}", path, e),
+        };
+
+        ShortenerCache {
+            path: path.to_string(),
+            expansions: Mutex::new(expansions),
+        }
+    }
+
+    pub(crate) fn get(&self, url: &str) -> Option<Vec<String>> {
+        self.expansions.lock().unwrap().get(url).map(|expansion| expansion.links.clone())
+    }
+
+    pub(crate) fn insert(&self, url: String, links: Vec<String>, now: u64) {
+        self.expansions.lock().unwrap().insert(url, Expansion {
+            expanded_at: now,
+            links,
+        });
+    }
+
+    pub(crate) fn save(&self, now: u64) {
+        let mut expansions = self.expansions.lock().unwrap();
+        expansions.retain(|_url, expansion| expansion.expanded_at + RETENTION > now);
+
+        let data = serde_json::to_string(&*expansions).expect("Can't serialize shortener cache");
+        atomic_write(&self.path, data.as_bytes()).expect("Can't write data to shortener cache file");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::fake::FakeClient;
+
+    #[tokio::test]
+    async fn expanding() {
+        let client = FakeClient::new()
+            .redirection("https://bit.ly/3xYzAbC", "https://gleam.io/7qHd6/sorteo")
+            .page("https://gleam.io/7qHd6/sorteo", 200, "<html></html>")
+            .redirection("https://linktr.ee/streamer", "https://example.com/links")
+            .page("https://example.com/links", 200, r#"<a href="https://gleam.io/3uSs9/taylor-moon">Giveaway</a>"#)
+            .page("https://bit.ly/gone", 404, "Not found");
+
+        assert_eq!(expand(&client, "https://bit.ly/3xYzAbC").await.unwrap(), vec![String::from("https://gleam.io/7qHd6/-")]);
+        assert_eq!(expand(&client, "https://linktr.ee/streamer").await.unwrap(), vec![String::from("https://gleam.io/3uSs9/-")]);
+        assert!(expand(&client, "https://bit.ly/gone").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn too_many_redirections() {
+        let client = FakeClient::new()
+            .max_redirects(2)
+            .redirection("https://bit.ly/3xYzAbC", "https://t.co/aBcDeF")
+            .redirection("https://t.co/aBcDeF", "https://gleam.io/7qHd6/sorteo")
+            .redirection("https://tinyurl.com/loop", "https://bit.ly/3xYzAbC")
+            .page("https://gleam.io/7qHd6/sorteo", 200, "<html></html>");
+
+        assert_eq!(expand(&client, "https://bit.ly/3xYzAbC").await.unwrap(), vec![String::from("https://gleam.io/7qHd6/-")]);
+        assert!(expand(&client, "https://tinyurl.com/loop").await.is_err());
+    }
+
+    #[test]
+    fn shorteners() {
+        let redirectors = vec![String::from("go.example.com")];
+        let urls = vec![
+            String::from("https://bit.ly/3xYzAbC?utm_source=twitter"),
+            String::from("https://www.bit.ly/3xYzAbC"),
+            String::from("https://bit.ly/"),
+            String::from("https://go.example.com/out?url=https%3A%2F%2Fgleam.io%2F7qHd6%2Fsorteo&utm_medium=social"),
+            String::from("https://notbit.ly/3xYzAbC"),
+            String::from("https://go.example.com/giveaway"),
+            String::from("https://example.com/giveaway"),
+            String::from("https://linktr.ee/streamer"),
+        ];
+        assert_eq!(short_links(urls, &redirectors), vec![
+            String::from("https://bit.ly/3xYzAbC"),
+            String::from("https://go.example.com/out?url=https%3A%2F%2Fgleam.io%2F7qHd6%2Fsorteo"),
+            String::from("https://go.example.com/giveaway"),
+            String::from("https://linktr.ee/streamer"),
+        ]);
+    }
+}
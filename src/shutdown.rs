@@ -0,0 +1,127 @@
+use std::{future::Future, sync::{Arc, atomic::{AtomicBool, Ordering}}, time::Duration};
+use tokio::{sync::Notify, time::delay_for};
+
+/// The streams of the signals asking the process to stop.
+/// They are created once, so that no signal is missed between two waits.
+#[cfg(unix)]
+struct Signals {
+    interrupt: tokio::signal::unix::Signal,
+    terminate: tokio::signal::unix::Signal,
+}
+
+#[cfg(unix)]
+impl Signals {
+    fn new() -> Signals {
+        use tokio::signal::unix::{signal, SignalKind};
+        Signals {
+            interrupt: signal(SignalKind::interrupt()).expect("Failed to listen to SIGINT"),
+            terminate: signal(SignalKind::terminate()).expect("Failed to listen to SIGTERM"),
+        }
+    }
+
+    async fn recv(&mut self) {
+        tokio::select! {
+            _ = self.interrupt.recv() => (),
+            _ = self.terminate.recv() => (),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct Signals;
+
+#[cfg(not(unix))]
+impl Signals {
+    fn new() -> Signals {
+        Signals
+    }
+
+    async fn recv(&mut self) {
+        tokio::signal::ctrl_c().await.expect("Failed to listen to Ctrl+C");
+    }
+}
+
+/// Tells the long running tasks that the process has been asked to stop.
+#[derive(Clone)]
+pub(crate) struct Shutdown {
+    requested: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl Shutdown {
+    /// Listen to SIGINT and SIGTERM.
+    /// The first signal requests a graceful shutdown and the second one exits immediately.
+    pub(crate) fn listen() -> Shutdown {
+        let shutdown = Shutdown {
+            requested: Arc::new(AtomicBool::new(false)),
+            notify: Arc::new(Notify::new()),
+        };
+
+        let listener = shutdown.clone();
+        tokio::spawn(async move {
+            let mut signals = Signals::new();
+            loop {
+                signals.recv().await;
+                if listener.requested.swap(true, Ordering::SeqCst) {
+                    eprintln!("Exiting immediately");
+                    std::process::exit(130);
+                }
+                eprintln!("Stopping after the current requests... Send the signal again to exit immediately.");
+                listener.notify.notify();
+            }
+        });
+
+        shutdown
+    }
+
+    pub(crate) fn requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Run `future` unless a shutdown is requested before it completes, in which case `None` is returned.
+    pub(crate) async fn interruptible<F: Future>(&self, future: F) -> Option<F::Output> {
+        if self.requested() {
+            return None;
+        }
+        tokio::select! {
+            output = future => Some(output),
+            _ = self.notify.notified() => {
+                // Only one waiting task is woken up, which wakes up the next one
+                self.notify.notify();
+                None
+            },
+        }
+    }
+
+    /// Sleep for `duration` unless a shutdown is requested in the meantime.
+    pub(crate) async fn sleep(&self, duration: Duration) {
+        self.interruptible(delay_for(duration)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn interrupting() {
+        let shutdown = Shutdown {
+            requested: Arc::new(AtomicBool::new(false)),
+            notify: Arc::new(Notify::new()),
+        };
+        assert_eq!(shutdown.interruptible(async { 1 }).await, Some(1));
+
+        // Every waiting task is interrupted
+        let waiting = (0..3).map(|_| {
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move { shutdown.interruptible(delay_for(Duration::from_secs(3600))).await })
+        }).collect::<Vec<_>>();
+        delay_for(Duration::from_millis(10)).await;
+        shutdown.requested.store(true, Ordering::SeqCst);
+        shutdown.notify.notify();
+        for task in waiting {
+            assert_eq!(task.await.unwrap(), None);
+        }
+        assert_eq!(shutdown.interruptible(async { 1 }).await, None);
+    }
+}
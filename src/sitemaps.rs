@@ -0,0 +1,127 @@
+use chrono::{DateTime, NaiveDate};
+use crate::{http::{self, HttpClient}, xml::{element_text, blocks}};
+
+/// A page, or a nested sitemap, listed in a sitemap.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Entry {
+    pub(crate) loc: String,
+    /// Timestamp of the last modification, if the sitemap tells it
+    pub(crate) lastmod: Option<u64>,
+}
+
+impl Entry {
+    /// Whether the entry changed after `since`. Entries without a date are assumed to have changed.
+    pub(crate) fn changed_since(&self, since: u64) -> bool {
+        self.lastmod.map(|lastmod| lastmod >= since).unwrap_or(true)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Sitemap {
+    /// A sitemap index, listing other sitemaps
+    Index(Vec<Entry>),
+    /// A sitemap listing pages
+    UrlSet(Vec<Entry>),
+}
+
+/// Parse a W3C datetime, as used in sitemaps: a date, optionally followed by a time and a timezone.
+fn parse_lastmod(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let value = match value.strip_suffix('Z') {
+        Some(value) => format!("{}+00:00", value),
+        None => value.to_string(),
+    };
+    let timestamp = if let Ok(datetime) = DateTime::parse_from_rfc3339(&value) {
+        datetime.timestamp()
+    } else if let Ok(datetime) = DateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M%:z") {
+        datetime.timestamp()
+    } else {
+        NaiveDate::parse_from_str(&value, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0)?.and_utc().timestamp()
+    };
+    if timestamp < 0 {
+        return None;
+    }
+    Some(timestamp as u64)
+}
+
+/// The `<name>` elements of `content` with a location.
+fn entries(content: &str, name: &str) -> Vec<Entry> {
+    blocks(content, name).into_iter().filter_map(|block| {
+        Some(Entry {
+            loc: element_text(block, "loc")?,
+            lastmod: element_text(block, "lastmod").and_then(|lastmod| parse_lastmod(&lastmod)),
+        })
+    }).collect()
+}
+
+pub(crate) fn parse(content: &str) -> Sitemap {
+    if content.contains("<sitemapindex") {
+        Sitemap::Index(entries(content, "sitemap"))
+    } else {
+        Sitemap::UrlSet(entries(content, "url"))
+    }
+}
+
+pub(crate) async fn fetch(client: &dyn HttpClient, url: &str) -> Result<Sitemap, http::Error> {
+    let headers = [
+        ("Accept", "application/xml,text/xml"),
+        ("User-Agent", "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:78.0) Gecko/20100101 Firefox/78.0"),
+    ];
+    let response = client.get(url, &headers).await?.error_for_status()?;
+    Ok(parse(&response.body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INDEX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <sitemap>
+    <loc>https://example.com/sitemap-giveaways.xml</loc>
+    <lastmod>2020-11-02T10:15:00+00:00</lastmod>
+  </sitemap>
+  <sitemap>
+    <loc>https://example.com/sitemap-pages.xml</loc>
+  </sitemap>
+</sitemapindex>"#;
+
+    const URLSET: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url><loc>https://example.com/giveaways?page=1&amp;sort=new</loc><lastmod>2020-11-02</lastmod></url>
+  <url>
+    <loc><![CDATA[https://example.com/giveaways/win-a-gpu]]></loc>
+    <lastmod>2020-11-01T08:30Z</lastmod>
+    <changefreq>daily</changefreq>
+  </url>
+  <url><loc>https://example.com/about</loc><lastmod>not a date</lastmod></url>
+  <url><lastmod>2020-11-02</lastmod></url>
+</urlset>"#;
+
+    #[test]
+    fn parsing() {
+        assert_eq!(parse(INDEX), Sitemap::Index(vec![
+            Entry { loc: String::from("https://example.com/sitemap-giveaways.xml"), lastmod: Some(1604312100) },
+            Entry { loc: String::from("https://example.com/sitemap-pages.xml"), lastmod: None },
+        ]));
+        assert_eq!(parse(URLSET), Sitemap::UrlSet(vec![
+            Entry { loc: String::from("https://example.com/giveaways?page=1&sort=new"), lastmod: Some(1604275200) },
+            Entry { loc: String::from("https://example.com/giveaways/win-a-gpu"), lastmod: Some(1604219400) },
+            Entry { loc: String::from("https://example.com/about"), lastmod: None },
+        ]));
+    }
+
+    #[test]
+    fn lastmod() {
+        assert_eq!(parse_lastmod("2020-11-02T12:15:00+02:00"), Some(1604312100));
+        assert_eq!(parse_lastmod("2020-11-02T10:15:00.25Z"), Some(1604312100));
+        assert_eq!(parse_lastmod("2020-11-02T12:15+02:00"), Some(1604312100));
+        assert_eq!(parse_lastmod(" 2020-11-02 "), Some(1604275200));
+        assert_eq!(parse_lastmod("yesterday"), None);
+
+        let entry = Entry { loc: String::from("https://example.com/"), lastmod: Some(100) };
+        assert!(entry.changed_since(100));
+        assert!(!entry.changed_since(101));
+        assert!(Entry { loc: String::from("https://example.com/"), lastmod: None }.changed_since(101));
+    }
+}
@@ -1,32 +1,46 @@
-use crate::config::*;
-use format::prelude::*;
-use std::fs::File;
-use std::io::prelude::*;
-use serde_json as json;
-use std::time::SystemTime;
-use std::process::exit;
+use crate::{config::*, database::open_database, seen::SeenStore, util::url_to_host};
+use std::{collections::HashMap, time::SystemTime};
 
-pub fn stats(config: Config) {
-    let mut file = match File::open(&config.database_file) {
-        Ok(file) => file,
-        Err(e) => {
-            eprintln!("Failed to open the file {}: {}", config.database_file, e);
-            exit(1);
+pub fn stats(config: Config, giveaway: Option<&str>) {
+    let database = open_database(&config);
+
+    if let Some(key) = giveaway {
+        for referer in database.referers(key) {
+            let kind = if referer.embedded { "embedded" } else { "linked" };
+            println!("{}\t{}\t{}\t{}\t{}", referer.first_seen, referer.last_seen, kind, referer.host, referer.url);
         }
-    };
-    
-    let mut content = String::new();
-    if let Err(e) = file.read_to_string(&mut content) {
-        eprintln!("Failed to read the file {}: {}", config.database_file, e);
-        exit(1);
+        return;
     }
 
-    let giveaways: Vec<SearchResult> = json::from_str(&content).unwrap();
-    let total = giveaways.len();
+    let total = database.count();
     let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
-    let running_giveaways: Vec<&SearchResult> = giveaways.iter().filter(|g| g.ends_at() > timestamp).collect();
+    let running = database.count_running(timestamp);
             
-    println!("running: \t{}", running_giveaways.len());
-    println!("ended: \t\t{}", total - running_giveaways.len());
+    println!("running: \t{}", running);
+    println!("ended: \t\t{}", total - running);
     println!("total: \t\t{}", total);
-}
\ No newline at end of file
+
+    let hosts = database.referer_hosts();
+    if !hosts.is_empty() {
+        println!();
+        println!("top referring hosts (giveaways):");
+        for (host, count) in hosts.iter().take(10) {
+            println!("{:>8}\t{}", count, host);
+        }
+    }
+
+    let seen = SeenStore::open(&config.seen_file, config.seen_ttl, HashMap::new());
+    let mut blocked_hosts: HashMap<String, usize> = HashMap::new();
+    for url in seen.blocked() {
+        *blocked_hosts.entry(url_to_host(url)).or_default() += 1;
+    }
+    if !blocked_hosts.is_empty() {
+        let mut blocked_hosts = blocked_hosts.into_iter().collect::<Vec<(String, usize)>>();
+        blocked_hosts.sort_by(|(host1, count1), (host2, count2)| count2.cmp(count1).then(host1.cmp(host2)));
+        println!();
+        println!("pages blocked by robots.txt: {}", blocked_hosts.iter().map(|(_, count)| count).sum::<usize>());
+        for (host, count) in blocked_hosts.iter().take(10) {
+            println!("{:>8}\t{}", count, host);
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use std::{fs::{self, File}, io::{self, prelude::*}};
+use url::Url;
+
+pub(crate) fn url_to_host(url: &str) -> String {
+    if let Ok(url) = &Url::parse(url) {
+        url.host_str().unwrap_or("unknown").to_string()
+    } else {
+        "unknown".to_string()
+    }
+}
+
+/// Replace the file at `path` with `data`, writing to a temporary file first so that it is never left half written.
+pub(crate) fn atomic_write(path: &str, data: &[u8]) -> io::Result<()> {
+    let temporary_path = format!("{}.tmp", path);
+    let mut file = File::create(&temporary_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&temporary_path, path)
+}
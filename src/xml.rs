@@ -0,0 +1,53 @@
+use crate::extract::decode_entities;
+
+/// The opening tags `<name ...>` of `xml`, as the index of their end and their attributes.
+pub(crate) fn opening_tags<'a>(xml: &'a str, name: &'a str) -> impl Iterator<Item = (usize, &'a str)> + 'a {
+    let pattern = format!("<{}", name);
+    xml.match_indices(&pattern).map(|(start, _)| start).collect::<Vec<_>>().into_iter().filter_map(move |start| {
+        let rest = &xml[start + name.len() + 1..];
+        if !rest.starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace()) {
+            return None;
+        }
+        let end = rest.find('>')?;
+        Some((start + name.len() + 1 + end + 1, rest[..end].trim_end_matches('/')))
+    })
+}
+
+/// The text of the first `<name>` element of `xml`, if it is not empty.
+pub(crate) fn element_text(xml: &str, name: &str) -> Option<String> {
+    let (start, _attributes) = opening_tags(xml, name).next()?;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    let text = xml[start..end].trim();
+    let text = match text.strip_prefix("<![CDATA[").and_then(|text| text.strip_suffix("]]>")) {
+        Some(text) => text.to_string(),
+        None => decode_entities(text),
+    };
+    Some(text.trim().to_string()).filter(|text| !text.is_empty())
+}
+
+/// The blocks of the `<name>` elements of `xml`.
+pub(crate) fn blocks<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let closing = format!("</{}>", name);
+    opening_tags(xml, name)
+        .map(|(start, _attributes)| {
+            let end = xml[start..].find(&closing).map(|end| start + end).unwrap_or(xml.len());
+            &xml[start..end]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elements() {
+        let xml = r#"<channel><title>Giveaways &amp; more</title><item><link>https://example.com/a</link></item><items/><item id="b"><link><![CDATA[https://example.com/b?a=1&b=2]]></link><guid> </guid></item></channel>"#;
+        let items = blocks(xml, "item");
+        assert_eq!(items.len(), 2);
+        assert_eq!(element_text(xml, "title"), Some(String::from("Giveaways & more")));
+        assert_eq!(element_text(items[1], "link"), Some(String::from("https://example.com/b?a=1&b=2")));
+        assert_eq!(element_text(items[1], "guid"), None);
+        assert_eq!(opening_tags(xml, "item").map(|(_end, attributes)| attributes).collect::<Vec<_>>(), vec!["", " id=\"b\""]);
+    }
+}